@@ -1,23 +1,31 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
     fs::{self, File},
+    hash::{DefaultHasher, Hash, Hasher},
     io::{Read, Write},
+    ops::RangeInclusive,
     path::{Path, PathBuf},
-    sync::{atomic::AtomicU32, Arc, OnceLock},
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, AtomicU32},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
-use clap::Parser;
-use hocg_fan_sim_assets_model::{CardEntry, CardsInfo};
+use clap::{Parser, ValueEnum};
+use hocg_fan_sim_assets_model::{is_card_number, CardEntry, CardsInfo};
+use image::{imageops::FilterType, DynamicImage, Rgb, RgbImage};
 use indexmap::IndexMap;
 use oxipng::{InFile, Options, OutFile};
 use parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use rayon::iter::{
-    IntoParallelIterator, IntoParallelRefIterator, ParallelBridge, ParallelIterator,
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelBridge,
+    ParallelIterator,
 };
 use reqwest::{
-    blocking::{Client, ClientBuilder},
-    header::{LAST_MODIFIED, REFERER},
+    blocking::{Client, ClientBuilder, Response},
+    header::{CONTENT_TYPE, LAST_MODIFIED, REFERER},
     Url,
 };
 use scraper::{Html, Selector};
@@ -29,6 +37,11 @@ use zip::write::SimpleFileOptions;
 
 static WEBP_QUALITY: f32 = 80.0;
 
+static DECK_TYPES: [&str; 3] = ["N", "OSHI", "YELL"];
+
+// stop a deck type after this many failed pages in a row, e.g. when rate limited
+static MAX_FAILED_PAGES: u32 = 3;
+
 fn http_client() -> &'static Client {
     static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
     HTTP_CLIENT.get_or_init(|| ClientBuilder::new().cookie_store(true).build().unwrap())
@@ -58,10 +71,28 @@ struct Args {
     #[arg(short = 'o', long)]
     optimized_original_images: bool,
 
+    /// Pick the WebP quality of each downloaded image to stay under this size, in KB
+    /// (not with -o, the original PNG images are kept as is)
+    #[arg(long, conflicts_with = "optimized_original_images")]
+    target_kb: Option<u32>,
+
+    /// Also save small WebP thumbnails of the downloaded images in this folder
+    /// (use with --force-download to create them for existing images)
+    #[arg(long)]
+    thumbnails: Option<PathBuf>,
+
+    /// The width of the thumbnails, in pixels
+    #[arg(long, default_value_t = 150, value_parser = clap::value_parser!(u32).range(1..))]
+    thumbnail_width: u32,
+
     /// Package the image into a zip file
     #[arg(short = 'z', long)]
     zip_images: bool,
 
+    /// Only update the changed images in the existing zip file
+    #[arg(long)]
+    zip_incremental: bool,
+
     /// Don't read existing file
     #[arg(short = 'c', long)]
     clean: bool,
@@ -70,6 +101,14 @@ struct Args {
     #[arg(short = 'p', long)]
     proxy_path: Option<PathBuf>,
 
+    /// Flatten transparent proxy images on this color e.g. #000000 (default to keep the transparency)
+    #[arg(long, value_parser = parse_hex_color)]
+    proxy_background: Option<[u8; 3]>,
+
+    /// Use a copy of the official image when there is no english proxy (see --reset-field)
+    #[arg(long)]
+    backfill_english_images: bool,
+
     /// The folder that contains the assets i.e. card info, images, proxies
     #[arg(long, default_value = "assets")]
     assets_path: PathBuf,
@@ -78,18 +117,326 @@ struct Args {
     #[arg(long)]
     skip_update: bool,
 
+    /// Run the update again every N minutes, until Ctrl-C
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    watch: Option<u64>,
+
+    /// Don't access the network, fail if an option needs it
+    #[arg(long)]
+    offline: bool,
+
     /// Update the yuyu-tei.jp urls for the cards. can only be use when all cards are searched
+    /// (same as --only-price-sources yuyutei)
     #[arg(long)]
     yuyutei_urls: bool,
 
+    /// Append each price update to this file as a JSON line
+    #[arg(long)]
+    price_feed: Option<PathBuf>,
+
+    /// Only update these price sources e.g. yuyutei
+    #[arg(long, value_enum, value_delimiter = ',')]
+    only_price_sources: Vec<PriceSource>,
+
+    /// List the released cards without a price url, as JSON, then exit
+    #[arg(long)]
+    list_missing_prices: bool,
+
+    /// Check that the stored price urls still work, then exit
+    #[arg(long)]
+    check_urls: bool,
+
+    /// Clear this field for all the cards, so it's imported again e.g. yuyutei-sell-url
+    #[arg(long, value_enum)]
+    reset_field: Vec<ResetField>,
+
+    /// Make sure a manage_id is only used once per card
+    #[arg(long)]
+    dedupe_manage_ids: bool,
+
+    /// Remove these sets and their images e.g. hYS01, then exit
+    #[arg(long, value_delimiter = ',')]
+    prune_sets: Vec<String>,
+
+    /// Don't ask for confirmation before removing or replacing anything
+    #[arg(long)]
+    yes: bool,
+
+    /// Recreate the cards info from the stored images if it was lost, then exit
+    #[arg(long)]
+    rebuild_from_images: bool,
+
+    /// Report the official images that are newer than the stored ones, then exit
+    #[arg(long)]
+    compare_images_to_source: bool,
+
+    /// Download the images reported by --compare-images-to-source
+    #[arg(long, requires = "compare_images_to_source")]
+    fix: bool,
+
+    /// Check the cards info for inconsistencies, then exit
+    #[arg(long)]
+    validate: bool,
+
+    /// Show how many elements the scraper selectors match, then exit
+    #[arg(long)]
+    dump_selectors: bool,
+
+    /// Report the stored images that are not card shaped, then exit
+    #[arg(long)]
+    audit_aspect: bool,
+
+    /// Report the manage_ids that are far from the others of their set, then exit
+    #[arg(long)]
+    verify_manage_id_monotonic: bool,
+
     /// Use holoDelta to import missing/unreleased cards data
     #[arg(long)]
     import_holodelta: bool,
+
+    /// Read the Deck Log cards from a saved JSON file instead of the website,
+    /// grouped by deck type i.e. {"N": [...], "OSHI": [...], "YELL": [...]}
+    #[arg(long)]
+    decklog_json: Option<PathBuf>,
+
+    /// Save the Deck Log cards to a JSON file that can be used with --decklog-json
+    #[arg(long)]
+    save_decklog_json: Option<PathBuf>,
+
+    /// What to do when an image cannot be decoded
+    #[arg(long, value_enum, default_value_t = OnImageError::Abort)]
+    on_image_error: OnImageError,
+
+    /// Only process the first N cards (images, proxies)
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Write a JSON report of what happened during the update
+    #[arg(long)]
+    import_report: Option<PathBuf>,
+
+    /// Write a lightweight index of all the cards, for client-side search
+    #[arg(long)]
+    emit_index: Option<PathBuf>,
+
+    /// Write a CSV of every image, with its card and language
+    #[arg(long)]
+    export_images_index: Option<PathBuf>,
+
+    /// Also save the cards info as one file per set in this folder, with an index.json
+    #[arg(long)]
+    split_by_set: Option<PathBuf>,
+
+    /// Load the cards info from a folder created with --split-by-set
+    #[arg(long)]
+    load_split_sets: Option<PathBuf>,
+
+    /// How to format the cards info file
+    #[arg(long, value_enum, default_value_t = JsonStyle::Pretty)]
+    json_style: JsonStyle,
+
+    /// Stop the scrapers after this many pages, in case the end is never detected
+    #[arg(long, default_value_t = 1000)]
+    max_pages: u32,
+
+    /// Apply the Deck Log pages in a fixed order, for the same output on every run
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Only process cards from these sets e.g. hSD01,hBP01 (default to all)
+    #[arg(long, value_delimiter = ',')]
+    include_sets: Vec<String>,
+
+    /// Don't process cards from these sets e.g. hPR,hYS01
+    #[arg(long, value_delimiter = ',')]
+    exclude_sets: Vec<String>,
+}
+
+impl Args {
+    /// The requested options that need to access the network
+    fn network_options(&self) -> Vec<&'static str> {
+        let mut options = Vec::new();
+        // the reports exit before the update
+        let report_only = self.list_missing_prices
+            || self.validate
+            || self.audit_aspect
+            || self.verify_manage_id_monotonic
+            || self.dump_selectors
+            || !self.prune_sets.is_empty()
+            || self.rebuild_from_images
+            || self.compare_images_to_source;
+        if !self.skip_update && self.decklog_json.is_none() && !report_only {
+            options.push("Deck Log update (use --skip-update or --decklog-json)");
+        }
+        if self.download_images {
+            options.push("--download-images");
+        }
+        if self.yuyutei_urls || !self.only_price_sources.is_empty() {
+            options.push("price sources");
+        }
+        if self.check_urls {
+            options.push("--check-urls");
+        }
+        if self.dump_selectors {
+            options.push("--dump-selectors");
+        }
+        if self.compare_images_to_source {
+            options.push("--compare-images-to-source");
+        }
+        options
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum JsonStyle {
+    /// Indented, one field per line
+    Pretty,
+    /// No whitespace, smallest file
+    Minified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum PriceSource {
+    /// yuyu-tei.jp sell urls
+    Yuyutei,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ResetField {
+    ImgLastModified,
+    ImgThumb,
+    ImgProxyEn,
+    /// Only the proxies added with --backfill-english-images
+    ImgProxyEnPlaceholder,
+    YuyuteiSellUrl,
+    DeltaArtIndex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OnImageError {
+    /// Log the error and continue with the next image
+    Skip,
+    /// Stop everything
+    Abort,
+}
+
+impl JsonStyle {
+    fn to_string<T: Serialize>(self, value: &T) -> String {
+        match self {
+            JsonStyle::Pretty => serde_json::to_string_pretty(value).unwrap(),
+            JsonStyle::Minified => serde_json::to_string(value).unwrap(),
+        }
+    }
+}
+
+struct SetFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl SetFilter {
+    fn matches(&self, card: &CardEntry) -> bool {
+        let set_code = card.set_code();
+        (self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(set_code)))
+            && !self
+                .exclude
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(set_code))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+struct ScrapeSummary {
+    added: u32,
+    updated: u32,
+    skipped: u32,
+    errors: u32,
+}
+
+impl Display for ScrapeSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} skipped, {} errors",
+            self.added, self.updated, self.skipped, self.errors
+        )
+    }
+}
+
+/// What happened during an update, for CI
+#[derive(Debug, Default, Serialize)]
+struct ImportReport {
+    phases: Vec<PhaseReport>,
+    deck_type_changes: Vec<DeckTypeChange>,
+    total_seconds: f64,
+}
+
+/// Deck legality and the card order depend on the deck type
+#[derive(Debug, Serialize)]
+struct DeckTypeChange {
+    card_number: String,
+    rare: String,
+    manage_id: u32,
+    before: String,
+    after: String,
+}
+
+#[derive(Debug, Serialize)]
+struct PhaseReport {
+    name: &'static str,
+    #[serde(flatten)]
+    summary: ScrapeSummary,
+    seconds: f64,
+}
+
+impl ImportReport {
+    fn add(&mut self, name: &'static str, summary: ScrapeSummary, start: Instant) {
+        self.phases.push(PhaseReport {
+            name,
+            summary,
+            seconds: start.elapsed().as_secs_f64(),
+        });
+    }
 }
 
 fn main() {
     let args = Args::parse();
 
+    if args.offline {
+        let network_options = args.network_options();
+        if !network_options.is_empty() {
+            eprintln!(
+                "ERROR: cannot use {} in offline mode",
+                network_options.join(", ")
+            );
+            std::process::exit(1);
+        }
+    }
+
+    // the cards info could be corrupted, don't load it
+    if args.rebuild_from_images {
+        if let Err(err) = check_assets_path(&args.assets_path) {
+            eprintln!("ERROR: {err}");
+            std::process::exit(1);
+        }
+        let card_mapping_file = args.assets_path.join("cards_info.json");
+        if card_mapping_file.exists() && !args.yes {
+            eprintln!(
+                "ERROR: {} already exists (use --yes to replace it)",
+                card_mapping_file.display()
+            );
+            std::process::exit(1);
+        }
+        let all_cards = rebuild_from_images(&args.assets_path);
+        write_atomic(&card_mapping_file, args.json_style.to_string(&all_cards));
+        return;
+    }
+
     let mut all_cards: CardsInfo = CardsInfo::new();
 
     // create a temporary folder for the zip file content
@@ -101,17 +448,194 @@ fn main() {
     };
 
     let card_mapping_file = assets_path.join("cards_info.json");
-    let images_path = assets_path.join("img");
-    let images_proxy_path = assets_path.join("img_proxy_en");
 
     // load file
     if !args.clean {
-        if let Ok(s) = fs::read_to_string(&card_mapping_file) {
+        if let Some(path) = &args.load_split_sets {
+            all_cards = load_split_by_set(path);
+        } else if let Ok(s) = fs::read_to_string(&card_mapping_file) {
             all_cards = serde_json::from_str(&s).unwrap();
         }
     }
 
-    let filtered_cards: Vec<(String, usize)> = if args.skip_update {
+    let set_filter = SetFilter {
+        include: args.include_sets.clone(),
+        exclude: args.exclude_sets.clone(),
+    };
+
+    // only report, don't update anything
+    if args.list_missing_prices {
+        list_missing_prices(&all_cards, &set_filter);
+        return;
+    }
+    if args.check_urls {
+        check_urls(&all_cards, &set_filter);
+        return;
+    }
+    if args.validate {
+        validate(&all_cards, &args.assets_path, &set_filter);
+        return;
+    }
+    if args.dump_selectors {
+        dump_selectors();
+        return;
+    }
+    if args.audit_aspect {
+        audit_aspect(&all_cards, &args.assets_path, &set_filter);
+        return;
+    }
+    if args.verify_manage_id_monotonic {
+        verify_manage_id_monotonic(&all_cards, &set_filter);
+        return;
+    }
+
+    // fail early, before scraping for a long time
+    if let Err(err) = check_assets_path(&args.assets_path) {
+        eprintln!("ERROR: {err}");
+        std::process::exit(1);
+    }
+
+    // clear the fields to import them again
+    for field in &args.reset_field {
+        reset_field(
+            &mut all_cards,
+            *field,
+            &set_filter,
+            &args.assets_path.join("img_proxy_en"),
+        );
+    }
+
+    // repair the database before updating it
+    if args.dedupe_manage_ids {
+        dedupe_manage_ids(&mut all_cards, &set_filter);
+    }
+
+    if args.compare_images_to_source {
+        compare_images_to_source(&mut all_cards, &args, &set_filter);
+        return;
+    }
+
+    if !args.prune_sets.is_empty() {
+        prune_sets(&mut all_cards, &args);
+        return;
+    }
+
+    if let Some(minutes) = args.watch {
+        // finish the current update before stopping
+        let stop = Arc::new(AtomicBool::new(false));
+        ctrlc::set_handler({
+            let stop = stop.clone();
+            move || {
+                println!("stopping after this update...");
+                stop.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+        })
+        .unwrap();
+
+        watch(Duration::from_secs(minutes * 60), &stop, || {
+            update(&args, &mut all_cards, assets_path, &set_filter)
+        });
+    } else {
+        update(&args, &mut all_cards, assets_path, &set_filter);
+    }
+
+    println!("done");
+}
+
+/// Run the update, then again after each interval until stopped.
+/// The stop flag is checked every second while waiting.
+fn watch(interval: Duration, stop: &AtomicBool, mut update: impl FnMut()) {
+    loop {
+        update();
+        if stop.load(std::sync::atomic::Ordering::Relaxed) {
+            break;
+        }
+        println!("next update in {} minutes", interval.as_secs() / 60);
+        let next_update = Instant::now() + interval;
+        loop {
+            if stop.load(std::sync::atomic::Ordering::Relaxed) {
+                return;
+            }
+            let remaining = next_update.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            std::thread::sleep(remaining.min(Duration::from_secs(1)));
+        }
+    }
+}
+
+/// Write to a temporary file next to the target, then rename it over the target.
+/// An interrupted save leaves the previous file intact.
+fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) {
+    write_atomic_with(path, |file| file.write_all(contents.as_ref())).unwrap();
+}
+
+/// Same as write_atomic, with the content written by a function that can fail
+fn write_atomic_with(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    // temporary files are only readable by the owner
+    let mut builder = tempfile::Builder::new();
+    if let Ok(metadata) = fs::metadata(path) {
+        builder.permissions(metadata.permissions());
+    } else {
+        #[cfg(unix)]
+        builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o644));
+    }
+    // the temporary file is deleted if anything fails
+    let mut file = builder.tempfile_in(parent)?;
+    write(file.as_file_mut())?;
+    file.as_file().sync_all()?;
+    file.persist(path)?;
+    Ok(())
+}
+
+/// Make sure the assets folder exists and can be written to
+fn check_assets_path(assets_path: &Path) -> Result<(), String> {
+    fs::create_dir_all(assets_path).map_err(|err| {
+        format!(
+            "cannot create the assets folder {}: {err}",
+            assets_path.display()
+        )
+    })?;
+    tempfile::tempfile_in(assets_path).map_err(|err| {
+        format!(
+            "cannot write in the assets folder {}: {err}",
+            assets_path.display()
+        )
+    })?;
+
+    for name in ["cards_info.json", "img", "img_proxy_en"] {
+        let path = assets_path.join(name);
+        let expected_dir = !name.ends_with(".json");
+        if path.exists() && path.is_dir() != expected_dir {
+            return Err(format!(
+                "{} should be a {}",
+                path.display(),
+                if expected_dir { "folder" } else { "file" }
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Update the cards info and the images, then save everything
+fn update(args: &Args, all_cards: &mut CardsInfo, assets_path: &Path, set_filter: &SetFilter) {
+    let card_mapping_file = assets_path.join("cards_info.json");
+    let images_path = assets_path.join("img");
+    let images_proxy_path = assets_path.join("img_proxy_en");
+
+    let mut report = ImportReport::default();
+    let start = Instant::now();
+
+    let mut filtered_cards: Vec<(String, usize)> = if args.skip_update {
         all_cards
             .values()
             .flat_map(|cs| cs.iter().enumerate())
@@ -120,51 +644,115 @@ fn main() {
             .map(|c| (c.1.card_number.clone(), c.0))
             .collect()
     } else {
+        // to compare with the imported ones
+        let previous_deck_types: HashMap<_, _> = all_cards
+            .values()
+            .flatten()
+            .filter_map(|c| Some((c.manage_id?, c.deck_type.clone())))
+            .collect();
+
         // import cards info from Deck Log
-        retrieve_card_info(
-            &mut all_cards,
-            &args.number_filter,
-            &args.expansion,
-            args.optimized_original_images,
-        )
+        let (filtered_cards, summary) = retrieve_card_info(all_cards, args, set_filter);
+        println!("Deck Log: {summary}");
+        report.add("deck_log", summary, start);
+
+        report.deck_type_changes = deck_type_changes(&previous_deck_types, all_cards);
+        for change in &report.deck_type_changes {
+            println!(
+                "DECK TYPE CHANGED: [{}, {}] - {} -> {}",
+                change.card_number, change.rare, change.before, change.after
+            );
+        }
+        filtered_cards
     };
 
+    select_cards(&mut filtered_cards, all_cards, set_filter, args.limit);
+
     // add official images
     if args.download_images {
-        download_images(
+        let start = Instant::now();
+        let summary = download_images(&filtered_cards, &images_path, all_cards, args);
+        report.add("images", summary, start);
+    }
+
+    // add proxy images
+    if let Some(path) = &args.proxy_path {
+        let start = Instant::now();
+        let summary = prepare_proxy_images(
             &filtered_cards,
-            &images_path,
-            &mut all_cards,
-            args.force_download,
-            args.optimized_original_images,
+            &images_proxy_path,
+            all_cards,
+            path.clone(),
+            args.on_image_error,
+            args.proxy_background,
         );
+        report.add("proxies", summary, start);
     }
 
-    // add proxy images
-    if let Some(path) = args.proxy_path {
-        prepare_proxy_images(&filtered_cards, &images_proxy_path, &mut all_cards, path);
+    // use the official images for the missing proxies
+    if args.backfill_english_images {
+        let start = Instant::now();
+        let summary =
+            backfill_english_images(&filtered_cards, &images_path, &images_proxy_path, all_cards);
+        report.add("backfill_english_images", summary, start);
+    }
+
+    // live feed of the price updates
+    let price_feed = args.price_feed.as_deref().map(PriceFeed::open);
+
+    let mut price_sources = args.only_price_sources.clone();
+    if args.yuyutei_urls && !price_sources.contains(&PriceSource::Yuyutei) {
+        price_sources.push(PriceSource::Yuyutei);
     }
 
     // update yuyutei price
-    if args.yuyutei_urls {
+    if price_sources.contains(&PriceSource::Yuyutei) {
         if args.number_filter.is_some() || args.expansion.is_some() {
             eprintln!("WARNING: SKIPPING YUYUTEI. ONLY AVAILABLE WHEN SEARCHING ALL CARDS.");
         } else {
-            yuyutei(&mut all_cards);
+            let start = Instant::now();
+            let summary = yuyutei(all_cards, set_filter, args.max_pages, price_feed.as_ref());
+            println!("Yuyutei: {summary}");
+            report.add("yuyutei", summary, start);
         }
     }
 
     // import from holoDelta
     if args.import_holodelta {
-        import_holodelta(&mut all_cards);
+        let start = Instant::now();
+        import_holodelta(all_cards);
+        let issues = validate_delta_art_index(all_cards, set_filter);
+        report.add(
+            "holodelta",
+            ScrapeSummary {
+                errors: issues,
+                ..Default::default()
+            },
+            start,
+        );
     }
 
     // save file
     if let Some(parent) = Path::new(&card_mapping_file).parent() {
         fs::create_dir_all(parent).unwrap();
     }
-    let json = serde_json::to_string_pretty(&all_cards).unwrap();
-    fs::write(card_mapping_file, json).unwrap();
+    let json = args.json_style.to_string(all_cards);
+    write_atomic(&card_mapping_file, json);
+
+    // also save one file per set
+    if let Some(path) = &args.split_by_set {
+        save_split_by_set(all_cards, path, args.json_style, set_filter);
+    }
+
+    // save the index for the card browser
+    if let Some(path) = &args.emit_index {
+        emit_index(all_cards, path, set_filter);
+    }
+
+    // save the images list for proxy makers
+    if let Some(path) = &args.export_images_index {
+        export_images_index(all_cards, path, set_filter);
+    }
 
     if args.zip_images {
         zip_images(
@@ -174,18 +762,77 @@ fn main() {
             ),
             &args.assets_path,
             &images_path,
+            args.zip_incremental,
         );
     }
 
-    println!("done");
+    if let Some(path) = &args.import_report {
+        report.total_seconds = start.elapsed().as_secs_f64();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        write_atomic(path, serde_json::to_string_pretty(&report).unwrap());
+    }
+}
+
+/// Only keep the cards from the selected sets, then the first N of them
+fn select_cards(
+    filtered_cards: &mut Vec<(String, usize)>,
+    all_cards: &CardsInfo,
+    set_filter: &SetFilter,
+    limit: Option<usize>,
+) {
+    filtered_cards.retain(|(card_number, card_idx)| {
+        all_cards
+            .get(card_number)
+            .and_then(|cs| cs.get(*card_idx))
+            .is_some_and(|c| set_filter.matches(c))
+    });
+
+    // only process a few cards, useful for testing
+    if let Some(limit) = limit {
+        filtered_cards.truncate(limit);
+    }
+}
+
+/// The cards with a different deck type than before the import, by manage_id
+fn deck_type_changes(
+    previous_deck_types: &HashMap<u32, String>,
+    all_cards: &CardsInfo,
+) -> Vec<DeckTypeChange> {
+    let mut changes = Vec::new();
+    for card in all_cards.values().flatten() {
+        let Some(manage_id) = card.manage_id else {
+            continue;
+        };
+        let Some(before) = previous_deck_types.get(&manage_id) else {
+            continue;
+        };
+        if !before.is_empty() && *before != card.deck_type {
+            changes.push(DeckTypeChange {
+                card_number: card.card_number.clone(),
+                rare: card.rare.clone(),
+                manage_id,
+                before: before.clone(),
+                after: card.deck_type.clone(),
+            });
+        }
+    }
+    changes
 }
 
 fn retrieve_card_info(
     all_cards: &mut CardsInfo,
-    number_filter: &Option<String>,
-    expansion: &Option<String>,
-    optimized_original_images: bool,
-) -> Vec<(String, usize)> {
+    args: &Args,
+    set_filter: &SetFilter,
+) -> (Vec<(String, usize)>, ScrapeSummary) {
+    let number_filter = &args.number_filter;
+    let expansion = &args.expansion;
+    let optimized_original_images = args.optimized_original_images;
+    let decklog_json = &args.decklog_json;
+    let deterministic = args.deterministic;
+    let max_pages = args.max_pages;
+
     if number_filter.is_none() && expansion.is_none() {
         println!("Retrieve ALL cards info");
     } else {
@@ -196,63 +843,123 @@ fn retrieve_card_info(
         );
     }
 
-    let filtered_cards = Arc::new(Mutex::new(Vec::new()));
-    let all_cards = Arc::new(RwLock::new(all_cards));
+    // saved Deck Log responses, grouped by deck type
+    let local_cards: Option<HashMap<String, Vec<CardEntry>>> = decklog_json.as_ref().map(|path| {
+        println!("Reading Deck Log cards from {}", path.display());
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    });
+    let local_cards = local_cards.as_ref();
+
+    let summary = Arc::new(Mutex::new(ScrapeSummary::default()));
+    // keep the pages to apply them after, to resolve the conflicts first
+    let pages = Arc::new(Mutex::new(Vec::new()));
 
-    let _ = ["N", "OSHI", "YELL"]
+    let _ = DECK_TYPES
         .into_par_iter()
+        .enumerate()
         .flat_map({
-            let filtered_cards = filtered_cards.clone();
-            let all_cards = all_cards.clone();
-            move |deck_type| {
-                (1..)
+            let summary = summary.clone();
+            let pages = pages.clone();
+            move |(deck_type_idx, deck_type)| {
+                let failed_pages = Arc::new(AtomicU32::new(0));
+                (1..=max_pages)
                     .par_bridge()
                     .map({
-                        let filtered_cards = filtered_cards.clone();
-                        let all_cards = all_cards.clone();
+                        let summary = summary.clone();
+                        let pages = pages.clone();
                         move |page| {
                             println!("deck type: {deck_type}, page: {page}");
 
-                            #[derive(Debug, Serialize)]
-                            #[serde(rename_all = "snake_case")]
-                            struct ApiSearchRequest {
-                                page: u32,
-                                param: ApiSearchParam,
-                            }
-                            #[derive(Debug, Serialize)]
-                            #[serde(rename_all = "snake_case")]
-                            struct ApiSearchParam {
-                                deck_param1: String,
-                                deck_type: String,
-                                keyword: String,
-                                keyword_type: Vec<String>,
-                                expansion: String,
-                            }
-
-                            let req = ApiSearchRequest {
-                                param: ApiSearchParam {
-                                    deck_param1: "S".into(),
-                                    deck_type: deck_type.into(),
-                                    keyword: number_filter.clone().unwrap_or_default(),
-                                    keyword_type: vec!["no".into()],
-                                    expansion: expansion.clone().unwrap_or_default(),
-                                },
-                                page,
-                            };
+                            let cards = if let Some(local_cards) = local_cards {
+                                // all the cards are in the first page
+                                if page > 1 {
+                                    Vec::new()
+                                } else {
+                                    local_cards
+                                        .get(deck_type)
+                                        .into_iter()
+                                        .flatten()
+                                        .filter(|c| {
+                                            number_filter.as_ref().is_none_or(|n| {
+                                                c.card_number
+                                                    .to_lowercase()
+                                                    .contains(&n.to_lowercase())
+                                            })
+                                        })
+                                        .filter(|c| {
+                                            expansion.as_ref().is_none_or(|x| {
+                                                x.eq_ignore_ascii_case(c.set_code())
+                                            })
+                                        })
+                                        .cloned()
+                                        .collect()
+                                }
+                            } else {
+                                #[derive(Debug, Serialize)]
+                                #[serde(rename_all = "snake_case")]
+                                struct ApiSearchRequest {
+                                    page: u32,
+                                    param: ApiSearchParam,
+                                }
+                                #[derive(Debug, Serialize)]
+                                #[serde(rename_all = "snake_case")]
+                                struct ApiSearchParam {
+                                    deck_param1: String,
+                                    deck_type: String,
+                                    keyword: String,
+                                    keyword_type: Vec<String>,
+                                    expansion: String,
+                                }
 
-                            let resp = http_client()
-                                .post("https://decklog.bushiroad.com/system/app/api/search/9")
-                                .header(REFERER, "https://decklog.bushiroad.com/")
-                                .json(&req)
-                                .send()
-                                .unwrap();
-
-                            let content = resp.text().unwrap();
-                            // println!("{content}");
-                            let cards = serde_json::from_str(&content);
-                            let Ok(cards): Result<Vec<CardEntry>, _> = cards else {
-                                eprintln!("didn't like response: {content}");
-                                panic!("{:?}", cards)
+                                let req = ApiSearchRequest {
+                                    param: ApiSearchParam {
+                                        deck_param1: "S".into(),
+                                        deck_type: deck_type.into(),
+                                        keyword: number_filter.clone().unwrap_or_default(),
+                                        keyword_type: vec!["no".into()],
+                                        expansion: expansion.clone().unwrap_or_default(),
+                                    },
+                                    page,
+                                };
+
+                                let content = http_client()
+                                    .post("https://decklog.bushiroad.com/system/app/api/search/9")
+                                    .header(REFERER, "https://decklog.bushiroad.com/")
+                                    .json(&req)
+                                    .send()
+                                    .map_err(|e| e.to_string())
+                                    .and_then(|resp| response_text(resp, "json"));
+                                // println!("{content}");
+                                let cards = content.and_then(|content| {
+                                    serde_json::from_str(&content).map_err(|e| {
+                                        format!("didn't like response: {e} ({content})")
+                                    })
+                                });
+                                // skip the page, the others can still be imported
+                                let cards: Vec<CardEntry> = match cards {
+                                    Ok(cards) => {
+                                        failed_pages.store(0, std::sync::atomic::Ordering::Relaxed);
+                                        cards
+                                    }
+                                    Err(e) => {
+                                        eprintln!(
+                                            "WARNING: deck type: {deck_type}, page: {page} skipped: {e}"
+                                        );
+                                        summary.lock().errors += 1;
+                                        let failed = failed_pages
+                                            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                                            + 1;
+                                        // don't keep going until max pages
+                                        if failed >= MAX_FAILED_PAGES {
+                                            eprintln!(
+                                                "WARNING: deck type: {deck_type} stopped after {failed} failed pages"
+                                            );
+                                            return None;
+                                        }
+                                        return Some(());
+                                    }
+                                };
+                                cards
                             };
 
                             // no more card in this page
@@ -260,57 +967,14 @@ fn retrieve_card_info(
                                 return None;
                             }
 
-                            // update records with deck type and webp images
-                            for mut card in cards {
-                                card.deck_type = deck_type.into();
-                                if !optimized_original_images {
-                                    card.img = card.img.replace(".png", ".webp");
-                                }
-
-                                // remove the old manage_id if it exists
-                                all_cards
-                                    .write()
-                                    .values_mut()
-                                    .flatten()
-                                    .filter(|c| {
-                                        c.manage_id == card.manage_id
-                                            && c.card_number != card.card_number
-                                    })
-                                    .for_each(|c| c.manage_id = None);
-
-                                // add the card the list
-                                let mut all_cards = all_cards.write();
-                                let list = all_cards.entry(card.card_number.clone()).or_default();
-                                // find the card, first by manage_id, then by image, then overwrite delta, otherwise just add
-                                if let Some(c) = {
-                                    if let Some(c) =
-                                        list.iter_mut().find(|c| c.manage_id == card.manage_id)
-                                    {
-                                        Some(c)
-                                    } else if let Some(c) =
-                                        list.iter_mut().find(|c| c.img == card.img)
-                                    {
-                                        Some(c)
-                                    } else {
-                                        list.iter_mut().find(|c| c.manage_id.is_none())
-                                    }
-                                } {
-                                    // only these fields are retrieved
-                                    c.card_number = card.card_number;
-                                    c.manage_id = card.manage_id;
-                                    c.rare = card.rare;
-                                    c.img = card.img;
-                                    c.max = card.max;
-                                    c.deck_type = card.deck_type;
-                                } else {
-                                    list.push(card.clone());
-                                }
-
-                                // sort the list, by oldest to latest
-                                list.sort_by_key(|c| c.manage_id);
+                            pages.lock().push((deck_type_idx, page, cards));
 
-                                // add to filtered cards
-                                filtered_cards.lock().push(card.manage_id);
+                            // the next pages could have more cards
+                            if page == max_pages {
+                                eprintln!(
+                                    "WARNING: deck type: {deck_type} stopped at --max-pages {max_pages}, there could be more cards"
+                                );
+                                summary.lock().errors += 1;
                             }
 
                             Some(())
@@ -321,27 +985,537 @@ fn retrieve_card_info(
         })
         .max(); // need this to drive the iterator
 
-    let all_cards = all_cards.read();
-    let filtered_cards = filtered_cards.lock();
-    all_cards
+    let mut pages = Arc::into_inner(pages).unwrap().into_inner();
+    let mut summary = Arc::into_inner(summary).unwrap().into_inner();
+
+    // apply the pages in the same order every time
+    if deterministic {
+        pages.sort_by_key(|(deck_type_idx, page, _)| (*deck_type_idx, *page));
+    }
+
+    if let Some(path) = &args.save_decklog_json {
+        save_decklog_json(&pages, path);
+    }
+
+    let claimed_manage_ids =
+        resolve_manage_id_conflicts(all_cards, pages.iter().flat_map(|(_, _, cards)| cards));
+
+    let mut filtered_cards = Vec::new();
+    for (deck_type_idx, _, mut cards) in pages {
+        // the images are converted to webp
+        if !optimized_original_images {
+            for card in &mut cards {
+                card.img = card.img.replace(".png", ".webp");
+            }
+        }
+        update_cards_from_decklog(
+            all_cards,
+            cards,
+            DECK_TYPES[deck_type_idx],
+            &mut filtered_cards,
+            &mut summary,
+            &claimed_manage_ids,
+            set_filter,
+        );
+    }
+
+    // some entries are duplicated, like hSD01-016
+    let merged = merge_duplicate_images(all_cards, set_filter);
+    if merged > 0 {
+        println!("Merged {merged} duplicate image entries");
+    }
+
+    let filtered_cards = all_cards
         .values()
         .flat_map(|cs| cs.iter().enumerate())
         .filter(|c| filtered_cards.contains(&c.1.manage_id))
         .map(|c| (c.1.card_number.clone(), c.0))
-        .collect()
+        .collect();
+
+    (filtered_cards, summary)
 }
 
-fn download_images(
-    filtered_cards: &[(String, usize)],
-    images_path: &Path,
-    all_cards: &mut CardsInfo,
-    force_download: bool,
-    optimized_original_images: bool,
-) {
-    println!("Downloading {} images...", filtered_cards.len());
+/// Save the pages as they were returned by Deck Log, grouped by deck type like --decklog-json
+fn save_decklog_json(pages: &[(usize, u32, Vec<CardEntry>)], path: &Path) {
+    let mut pages: Vec<_> = pages.iter().collect();
+    pages.sort_by_key(|(deck_type_idx, page, _)| (*deck_type_idx, *page));
+
+    let mut local_cards: BTreeMap<&str, Vec<&CardEntry>> = BTreeMap::new();
+    for (deck_type_idx, _, cards) in pages {
+        local_cards
+            .entry(DECK_TYPES[*deck_type_idx])
+            .or_default()
+            .extend(cards);
+    }
 
-    let all_cards = Arc::new(RwLock::new(all_cards));
-    let image_count = AtomicU32::new(0);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    write_atomic(path, serde_json::to_string_pretty(&local_cards).unwrap());
+    println!("Saved Deck Log cards to {}", path.display());
+}
+
+/// When Deck Log returns the same manage_id for multiple card numbers, pick the one to keep:
+/// the card number that already has it, otherwise the lowest one. Doesn't depend on the page order.
+fn resolve_manage_id_conflicts<'a>(
+    all_cards: &CardsInfo,
+    cards: impl Iterator<Item = &'a CardEntry>,
+) -> HashMap<u32, String> {
+    let mut claimants: HashMap<u32, BTreeSet<&str>> = HashMap::new();
+    for card in cards {
+        if let Some(manage_id) = card.manage_id {
+            claimants
+                .entry(manage_id)
+                .or_default()
+                .insert(&card.card_number);
+        }
+    }
+
+    let mut winners = HashMap::new();
+    for (manage_id, card_numbers) in claimants {
+        if card_numbers.len() < 2 {
+            continue;
+        }
+        let stored = card_numbers.iter().find(|n| {
+            all_cards
+                .get(**n)
+                .into_iter()
+                .flatten()
+                .any(|c| c.manage_id == Some(manage_id))
+        });
+        let winner = stored.unwrap_or_else(|| card_numbers.first().unwrap());
+        winners.insert(manage_id, winner.to_string());
+    }
+    winners
+}
+
+/// Merge the entries of a card that point to the same image, keeping the oldest manage_id
+fn merge_duplicate_images(all_cards: &mut CardsInfo, set_filter: &SetFilter) -> u32 {
+    let mut merged = 0;
+
+    // all the entries of a card number are in the same set
+    for list in all_cards
+        .values_mut()
+        .filter(|cs| cs.first().is_some_and(|c| set_filter.matches(c)))
+    {
+        let mut kept: Vec<CardEntry> = Vec::with_capacity(list.len());
+        // the list is sorted by manage_id, unreleased first
+        for card in list.drain(..) {
+            if let Some(c) = kept.iter_mut().find(|c| c.img == card.img) {
+                println!(
+                    "Duplicate image: {} ({:?}, {:?})",
+                    card.img, c.manage_id, card.manage_id
+                );
+                c.manage_id = c.manage_id.or(card.manage_id);
+                c.img_last_modified = c.img_last_modified.take().or(card.img_last_modified);
+                c.img_thumb = c.img_thumb.take().or(card.img_thumb);
+                // a real proxy is better than a placeholder
+                let has_proxy = c.img_proxy_en.is_some() && !c.img_proxy_en_placeholder;
+                if !has_proxy
+                    && card.img_proxy_en.is_some()
+                    && (c.img_proxy_en.is_none() || !card.img_proxy_en_placeholder)
+                {
+                    c.img_proxy_en = card.img_proxy_en;
+                    c.img_proxy_en_placeholder = card.img_proxy_en_placeholder;
+                }
+                c.yuyutei_sell_url = c.yuyutei_sell_url.take().or(card.yuyutei_sell_url);
+                c.delta_art_index = c.delta_art_index.or(card.delta_art_index);
+                if c.max_override.is_none() {
+                    if let Some(max_override) = card.max_override {
+                        c.max_override = Some(max_override);
+                        c.max = max_override;
+                    }
+                }
+                merged += 1;
+            } else {
+                kept.push(card);
+            }
+        }
+        *list = kept;
+    }
+
+    merged
+}
+
+/// Only keep what can be found from the file names e.g. hSD01/hSD01-001_RR.webp.
+/// The Deck Log import matches these entries by image, to fill in the rest.
+fn rebuild_from_images(assets_path: &Path) -> CardsInfo {
+    let images_path = assets_path.join("img");
+    let images_proxy_path = assets_path.join("img_proxy_en");
+
+    // relative path, with forward slashes like Deck Log
+    let relative_path = |path: &Path, prefix: &Path| {
+        path.strip_prefix(prefix)
+            .unwrap()
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+
+    let proxies: HashMap<_, _> = WalkDir::new(&images_proxy_path)
+        .into_iter()
+        .flatten()
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| {
+            let file_stem = e.path().file_stem()?.to_owned();
+            Some((file_stem, relative_path(e.path(), &images_proxy_path)))
+        })
+        .collect();
+
+    let mut all_cards = CardsInfo::new();
+    let mut ignored = 0;
+    for entry in WalkDir::new(&images_path)
+        .sort_by_file_name()
+        .into_iter()
+        .flatten()
+        .filter(|e| e.path().is_file())
+    {
+        let path = entry.path();
+        let Some(file_stem) = path.file_stem() else {
+            continue;
+        };
+        let Some((card_number, rare)) = file_stem
+            .to_str()
+            .and_then(|s| s.split_once('_'))
+            .filter(|(card_number, _)| is_card_number(card_number))
+        else {
+            eprintln!("WARNING: not a card image: {}", path.display());
+            ignored += 1;
+            continue;
+        };
+
+        let img_proxy_en = proxies.get(file_stem).cloned();
+        all_cards
+            .entry(card_number.into())
+            .or_default()
+            .push(CardEntry {
+                card_number: card_number.into(),
+                manage_id: None,
+                rare: rare.into(),
+                img: relative_path(path, &images_path),
+                // filled by Deck Log
+                max: 0,
+                max_override: None,
+                deck_type: String::new(),
+                img_last_modified: None,
+                img_thumb: None,
+                img_proxy_en,
+                img_proxy_en_placeholder: false,
+                yuyutei_sell_url: None,
+                delta_art_index: None,
+            });
+    }
+
+    println!(
+        "Rebuilt {} cards from {} images ({ignored} ignored)",
+        all_cards.len(),
+        all_cards.values().flatten().count()
+    );
+    all_cards
+}
+
+/// Remove all the cards of some sets, with the images that are not used anymore
+fn prune_sets(all_cards: &mut CardsInfo, args: &Args) {
+    let pruned = |card: &CardEntry| {
+        args.prune_sets
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(card.set_code()))
+    };
+
+    let removed: Vec<_> = all_cards
+        .values()
+        .flatten()
+        .filter(|c| pruned(c))
+        .cloned()
+        .collect();
+    if removed.is_empty() {
+        println!("No cards to remove");
+        return;
+    }
+    for card in &removed {
+        println!(
+            "REMOVE: [{}, {}] - {:?}",
+            card.card_number, card.rare, card.manage_id
+        );
+    }
+
+    if !args.yes {
+        print!("Remove {} cards and their images? [y/N] ", removed.len());
+        std::io::stdout().flush().unwrap();
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).unwrap();
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            println!("Nothing removed");
+            return;
+        }
+    }
+
+    let removed_count = removed.len();
+    for list in all_cards.values_mut() {
+        list.retain(|c| !pruned(c));
+    }
+    all_cards.retain(|_, list| !list.is_empty());
+
+    // some images could still be used by other cards
+    let images_path = args.assets_path.join("img");
+    let images_proxy_path = args.assets_path.join("img_proxy_en");
+    let used: Vec<PathBuf> = all_cards
+        .values()
+        .flatten()
+        .flat_map(|c| {
+            [
+                Some(images_path.join(&c.img)),
+                c.img_proxy_en
+                    .as_ref()
+                    .map(|img| images_proxy_path.join(img)),
+                c.img_thumb
+                    .as_ref()
+                    .zip(args.thumbnails.as_ref())
+                    .map(|(img, path)| path.join(img)),
+            ]
+        })
+        .flatten()
+        .collect();
+    let mut removed_files = 0;
+    for card in removed {
+        let files = [
+            (!card.img.trim().is_empty()).then(|| images_path.join(&card.img)),
+            // placeholders are copied files too
+            card.img_proxy_en
+                .as_ref()
+                .map(|img| images_proxy_path.join(img)),
+            card.img_thumb
+                .as_ref()
+                .zip(args.thumbnails.as_ref())
+                .map(|(img, path)| path.join(img)),
+        ];
+        for file in files.into_iter().flatten() {
+            if used.contains(&file) || !file.is_file() {
+                continue;
+            }
+            fs::remove_file(&file).unwrap();
+            removed_files += 1;
+        }
+    }
+
+    let card_mapping_file = args.assets_path.join("cards_info.json");
+    write_atomic(&card_mapping_file, args.json_style.to_string(all_cards));
+
+    println!("{removed_count} cards and {removed_files} images removed");
+}
+
+fn reset_field(
+    all_cards: &mut CardsInfo,
+    field: ResetField,
+    set_filter: &SetFilter,
+    images_proxy_path: &Path,
+) {
+    // placeholders are copies of the official images, not used anymore once cleared
+    let remove_placeholder = |card: &CardEntry| {
+        if let Some(img_proxy_en) = card
+            .img_proxy_en
+            .as_ref()
+            .filter(|_| card.img_proxy_en_placeholder)
+        {
+            let path = images_proxy_path.join(img_proxy_en);
+            if path.is_file() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    };
+
+    let mut count = 0;
+    for card in all_cards
+        .values_mut()
+        .flatten()
+        .filter(|c| set_filter.matches(c))
+    {
+        let was_set = match field {
+            ResetField::ImgLastModified => card.img_last_modified.take().is_some(),
+            ResetField::ImgThumb => card.img_thumb.take().is_some(),
+            ResetField::ImgProxyEn => {
+                remove_placeholder(card);
+                card.img_proxy_en_placeholder = false;
+                card.img_proxy_en.take().is_some()
+            }
+            ResetField::ImgProxyEnPlaceholder => {
+                let was_set = card.img_proxy_en_placeholder;
+                if was_set {
+                    remove_placeholder(card);
+                    card.img_proxy_en = None;
+                    card.img_proxy_en_placeholder = false;
+                }
+                was_set
+            }
+            ResetField::YuyuteiSellUrl => card.yuyutei_sell_url.take().is_some(),
+            ResetField::DeltaArtIndex => card.delta_art_index.take().is_some(),
+        };
+        if was_set {
+            count += 1;
+        }
+    }
+
+    println!("{count} {field:?} cleared");
+}
+
+/// Keep the first entry with a given manage_id, the other entries become unreleased
+fn dedupe_manage_ids(all_cards: &mut CardsInfo, set_filter: &SetFilter) {
+    let mut removed = 0;
+
+    // all the entries of a card number are in the same set
+    for list in all_cards
+        .values_mut()
+        .filter(|cs| cs.first().is_some_and(|c| set_filter.matches(c)))
+    {
+        let mut seen = Vec::new();
+        for card in list.iter_mut() {
+            let Some(manage_id) = card.manage_id else {
+                continue;
+            };
+            if seen.contains(&manage_id) {
+                println!(
+                    "Duplicate manage_id: [{}, {}] - {manage_id} ({})",
+                    card.card_number, card.rare, card.img
+                );
+                card.manage_id = None;
+                removed += 1;
+            } else {
+                seen.push(manage_id);
+            }
+        }
+        // sort the list, by oldest to latest
+        list.sort_by_key(|c| c.manage_id);
+    }
+
+    println!("{removed} duplicate manage_ids removed");
+}
+
+/// Add or update the cards of a Deck Log page, with the images already renamed
+fn update_cards_from_decklog(
+    all_cards: &mut CardsInfo,
+    cards: Vec<CardEntry>,
+    deck_type: &str,
+    filtered_cards: &mut Vec<Option<u32>>,
+    summary: &mut ScrapeSummary,
+    claimed_manage_ids: &HashMap<u32, String>,
+    set_filter: &SetFilter,
+) {
+    // update records with deck type and webp images
+    for mut card in cards {
+        // the other sets are kept as they are
+        if !set_filter.matches(&card) {
+            summary.skipped += 1;
+            continue;
+        }
+
+        card.deck_type = deck_type.into();
+
+        // don't let two card numbers steal the manage_id from each other
+        if let Some(manage_id) = card.manage_id {
+            let claimed_by = claimed_manage_ids.get(&manage_id);
+            if let Some(claimed_by) = claimed_by.filter(|n| **n != card.card_number) {
+                eprintln!(
+                    "WARNING: [{}, {}] - manage_id {manage_id} already used by {claimed_by}, ignored",
+                    card.card_number, card.rare
+                );
+                summary.errors += 1;
+                continue;
+            }
+        }
+
+        // remove the old manage_id if it exists
+        all_cards
+            .values_mut()
+            .flatten()
+            .filter(|c| c.manage_id == card.manage_id && c.card_number != card.card_number)
+            .filter(|c| set_filter.matches(c))
+            .for_each(|c| c.manage_id = None);
+
+        // add the card the list
+        let list = all_cards.entry(card.card_number.clone()).or_default();
+        let mut manage_id = card.manage_id;
+        // find the card, first by manage_id, then by image, then overwrite delta, otherwise just add
+        if let Some(c) = {
+            if let Some(c) = list.iter_mut().find(|c| c.manage_id == card.manage_id) {
+                Some(c)
+            } else if let Some(c) = list
+                .iter_mut()
+                .find(|c| !card.img.is_empty() && c.img == card.img)
+            {
+                // some entries are duplicated, like hSD01-016, keep the oldest manage_id
+                if let (Some(existing), Some(incoming)) = (c.manage_id, card.manage_id) {
+                    manage_id = Some(existing.min(incoming));
+                }
+                Some(c)
+            } else {
+                list.iter_mut().find(|c| c.manage_id.is_none())
+            }
+        } {
+            let unchanged = c.card_number == card.card_number
+                && c.manage_id == manage_id
+                && c.rare == card.rare
+                && c.img == card.img
+                && c.max == c.max_override.unwrap_or(card.max)
+                && c.deck_type == card.deck_type;
+            if unchanged {
+                summary.skipped += 1;
+            } else {
+                summary.updated += 1;
+            }
+
+            // only these fields are retrieved, don't overwrite with empty values
+            c.card_number = card.card_number;
+            c.manage_id = manage_id;
+            if !card.rare.trim().is_empty() {
+                c.rare = card.rare;
+            }
+            if !card.img.trim().is_empty() {
+                c.img = card.img;
+            }
+            c.max = card.max;
+            c.deck_type = card.deck_type;
+
+            // keep the manual correction
+            if let Some(max_override) = c.max_override {
+                if max_override != c.max {
+                    println!(
+                        "MAX OVERRIDE: [{}, {}] - {max_override} (Deck Log: {})",
+                        c.card_number, c.rare, c.max
+                    );
+                }
+                c.max = max_override;
+            }
+        } else {
+            list.push(card.clone());
+            summary.added += 1;
+        }
+
+        // sort the list, by oldest to latest
+        list.sort_by_key(|c| c.manage_id);
+
+        // add to filtered cards
+        filtered_cards.push(manage_id);
+    }
+}
+
+fn download_images(
+    filtered_cards: &[(String, usize)],
+    images_path: &Path,
+    all_cards: &mut CardsInfo,
+    args: &Args,
+) -> ScrapeSummary {
+    println!("Downloading {} images...", filtered_cards.len());
+
+    let force_download = args.force_download;
+    let optimized_original_images = args.optimized_original_images;
+    let on_image_error = args.on_image_error;
+    let thumbnails_path = args.thumbnails.as_deref();
+    let thumbnail_width = args.thumbnail_width;
+    let target_kb = args.target_kb;
+
+    let all_cards = Arc::new(RwLock::new(all_cards));
+    let image_count = AtomicU32::new(0);
     let image_skipped = AtomicU32::new(0);
 
     filtered_cards.par_iter().for_each({
@@ -349,9 +1523,8 @@ fn download_images(
         let image_count = &image_count;
         let image_skipped = &image_skipped;
         move |(card_number, card_idx)| {
-            // https://hololive-official-cardgame.com/wp-content/images/cardlist/hSD01/hSD01-006_RR.png
-
             let img_last_modified;
+            let img_thumb;
             // scope for the read guard
             {
                 let card = RwLockReadGuard::map(all_cards.read(), |ac| {
@@ -359,15 +1532,28 @@ fn download_images(
                 });
 
                 // check if it's a new image
+                let img_url = official_image_url(&card.img);
                 let resp = http_client()
-                    .head(format!(
-                        "https://hololive-official-cardgame.com/wp-content/images/cardlist/{}",
-                        card.img.replace(".webp", ".png")
-                    ))
+                    .head(&img_url)
                     .header(REFERER, "https://decklog.bushiroad.com/")
                     .send()
                     .unwrap();
 
+                // the official images are only served as png
+                let content_type = resp
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|h| h.to_str().ok())
+                    .unwrap_or_default();
+                if !resp.status().is_success() || !content_type.starts_with("image/") {
+                    eprintln!(
+                        "WARNING: {img_url} is not an image ({}, {content_type})",
+                        resp.status()
+                    );
+                    image_skipped.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    return;
+                }
+
                 let last_modified = resp
                     .headers()
                     .get(LAST_MODIFIED)
@@ -395,16 +1581,17 @@ fn download_images(
 
                 // download the image
                 let resp = http_client()
-                    .get(format!(
-                        "https://hololive-official-cardgame.com/wp-content/images/cardlist/{}",
-                        card.img.replace(".webp", ".png")
-                    ))
+                    .get(&img_url)
                     .header(REFERER, "https://decklog.bushiroad.com/")
                     .send()
                     .unwrap();
 
                 // Using `image` crate, open the included .jpg file
-                let img = image::load_from_memory(&resp.bytes().unwrap()).unwrap();
+                let Some(img) = load_image(&resp.bytes().unwrap(), &card.img, on_image_error)
+                else {
+                    image_skipped.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    return;
+                };
 
                 if optimized_original_images {
                     let path = images_path.join(&card.img);
@@ -421,22 +1608,35 @@ fn download_images(
                     )
                     .unwrap();
                 } else {
-                    // Create the WebP encoder for the above image
-                    let encoder: Encoder = Encoder::from_image(&img).unwrap();
-                    // Encode the image at a specified quality 0-100
-                    let webp: WebPMemory = encoder.encode(WEBP_QUALITY);
+                    // Encode the image at a specified quality 0-100, or to fit the target size
+                    let webp: WebPMemory = if let Some(target_kb) = target_kb {
+                        encode_webp_to_size(&img, target_kb as u64 * 1024, &card.img)
+                    } else {
+                        Encoder::from_image(&img).unwrap().encode(WEBP_QUALITY)
+                    };
                     // Define and write the WebP-encoded file to a given path
-                    let path = images_path.join(card.img.replace(".png", ".webp"));
+                    let path = images_path.join(Path::new(&card.img).with_extension("webp"));
                     if let Some(parent) = Path::new(&path).parent() {
                         fs::create_dir_all(parent).unwrap();
                     }
                     std::fs::write(&path, &*webp).unwrap();
                 }
 
+                img_thumb = if let Some(thumbnails_path) = thumbnails_path {
+                    Some(save_thumbnail(
+                        &img,
+                        thumbnails_path,
+                        &card.img,
+                        thumbnail_width,
+                    ))
+                } else {
+                    card.img_thumb.clone()
+                };
+
                 image_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                 let image_count = image_count.load(std::sync::atomic::Ordering::Relaxed);
                 let image_skipped = image_skipped.load(std::sync::atomic::Ordering::Relaxed);
-                if image_count % 10 == 0 {
+                if image_count.is_multiple_of(10) {
                     println!("{image_count} images downloaded ({image_skipped} skipped)");
                 }
             }
@@ -446,12 +1646,125 @@ fn download_images(
                 ac.get_mut(card_number).unwrap().get_mut(*card_idx).unwrap()
             });
             card.img_last_modified = img_last_modified;
+            card.img_thumb = img_thumb;
         }
     });
 
     let image_count = image_count.load(std::sync::atomic::Ordering::Relaxed);
     let image_skipped = image_skipped.load(std::sync::atomic::Ordering::Relaxed);
     println!("{image_count} images downloaded ({image_skipped} skipped)");
+
+    ScrapeSummary {
+        added: image_count,
+        skipped: image_skipped,
+        ..Default::default()
+    }
+}
+
+/// Binary search the highest WebP quality that fits in the size
+fn encode_webp_to_size(img: &DynamicImage, max_size: u64, name: &str) -> WebPMemory {
+    let encoder: Encoder = Encoder::from_image(img).unwrap();
+
+    let (mut low, mut high) = (0, 100);
+    let mut best = None;
+    while low <= high {
+        let quality = (low + high) / 2;
+        let webp = encoder.encode(quality as f32);
+        if webp.len() as u64 <= max_size {
+            best = Some(webp);
+            low = quality + 1;
+        } else if quality == 0 {
+            break;
+        } else {
+            high = quality - 1;
+        }
+    }
+
+    best.unwrap_or_else(|| {
+        eprintln!(
+            "WARNING: {name} is larger than {} KB even at the lowest quality",
+            max_size / 1024
+        );
+        encoder.encode(0.0)
+    })
+}
+
+/// Save a smaller WebP version of the image, returns its path in the thumbnails folder
+fn save_thumbnail(
+    img: &DynamicImage,
+    thumbnails_path: &Path,
+    card_img: &str,
+    width: u32,
+) -> String {
+    let thumb = img.resize(width, u32::MAX, FilterType::Lanczos3);
+
+    let encoder: Encoder = Encoder::from_image(&thumb).unwrap();
+    let webp: WebPMemory = encoder.encode(WEBP_QUALITY);
+    let img_thumb = Path::new(card_img).with_extension("webp");
+    let path = thumbnails_path.join(&img_thumb);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    fs::write(&path, &*webp).unwrap();
+
+    img_thumb.to_string_lossy().into_owned()
+}
+
+/// Convert to 8-bit RGBA, or to RGB on top of the background color
+fn handle_alpha(img: DynamicImage, background: Option<[u8; 3]>) -> DynamicImage {
+    if !img.color().has_alpha() {
+        return DynamicImage::ImageRgb8(img.into_rgb8());
+    }
+
+    let img = img.into_rgba8();
+    let Some(background) = background else {
+        return DynamicImage::ImageRgba8(img);
+    };
+
+    let flattened = RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        let a = a as u32;
+        let blend = |c: u8, bg: u8| ((c as u32 * a + bg as u32 * (255 - a)) / 255) as u8;
+        Rgb([
+            blend(r, background[0]),
+            blend(g, background[1]),
+            blend(b, background[2]),
+        ])
+    });
+    DynamicImage::ImageRgb8(flattened)
+}
+
+/// Parse a color like #ffffff
+fn parse_hex_color(s: &str) -> Result<[u8; 3], String> {
+    let hex = s.trim_start_matches('#');
+    // slicing by byte below
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("expected a color like #ffffff, got {s}"));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| e.to_string());
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn load_image(bytes: &[u8], name: &str, on_image_error: OnImageError) -> Option<DynamicImage> {
+    match image::load_from_memory(bytes) {
+        Ok(img) => Some(img),
+        Err(err) => match on_image_error {
+            OnImageError::Skip => {
+                eprintln!("WARNING: skipping {name}, cannot decode image: {err}");
+                None
+            }
+            OnImageError::Abort => panic!("cannot decode image {name}: {err}"),
+        },
+    }
+}
+
+/// The official image url, whatever the extension of the stored image
+/// e.g. https://hololive-official-cardgame.com/wp-content/images/cardlist/hSD01/hSD01-006_RR.png
+fn official_image_url(img: &str) -> String {
+    format!(
+        "https://hololive-official-cardgame.com/wp-content/images/cardlist/{}",
+        Path::new(img).with_extension("png").to_string_lossy()
+    )
 }
 
 fn prepare_proxy_images(
@@ -459,7 +1772,9 @@ fn prepare_proxy_images(
     images_proxy_path: &Path,
     all_cards: &mut CardsInfo,
     proxy_path: PathBuf,
-) {
+    on_image_error: OnImageError,
+    proxy_background: Option<[u8; 3]>,
+) -> ScrapeSummary {
     if !proxy_path.is_dir() {
         panic!("proxy_path should be dir");
     }
@@ -512,7 +1827,17 @@ fn prepare_proxy_images(
                 };
 
                 // Using `image` crate, open the included .jpg file
-                let img = image::load_from_memory(&fs::read(path).unwrap()).unwrap();
+                let Some(img) = load_image(
+                    &fs::read(path).unwrap(),
+                    &path.to_string_lossy(),
+                    on_image_error,
+                ) else {
+                    image_skipped.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    return;
+                };
+
+                // WebP only supports 8-bit RGB(A), keep or flatten the transparency
+                let img = handle_alpha(img, proxy_background);
 
                 // Create the WebP encoder for the above image
                 let encoder: Encoder = Encoder::from_image(&img).unwrap();
@@ -530,7 +1855,7 @@ fn prepare_proxy_images(
                 image_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                 let image_count = image_count.load(std::sync::atomic::Ordering::Relaxed);
                 let image_skipped = image_skipped.load(std::sync::atomic::Ordering::Relaxed);
-                if image_count % 10 == 0 {
+                if image_count.is_multiple_of(10) {
                     println!("{image_count} images copied ({image_skipped} skipped)");
                 }
             }
@@ -540,16 +1865,79 @@ fn prepare_proxy_images(
                 ac.get_mut(card_number).unwrap().get_mut(*card_idx).unwrap()
             });
             card.img_proxy_en = img_proxy_en;
+            card.img_proxy_en_placeholder = false;
         }
     });
 
     let image_count = image_count.load(std::sync::atomic::Ordering::Relaxed);
     let image_skipped = image_skipped.load(std::sync::atomic::Ordering::Relaxed);
     println!("{image_count} images copied ({image_skipped} not found)");
+
+    ScrapeSummary {
+        added: image_count,
+        skipped: image_skipped,
+        ..Default::default()
+    }
+}
+
+/// Copy the official image as a placeholder proxy, a real proxy will replace it
+fn backfill_english_images(
+    filtered_cards: &[(String, usize)],
+    images_path: &Path,
+    images_proxy_path: &Path,
+    all_cards: &mut CardsInfo,
+) -> ScrapeSummary {
+    let mut summary = ScrapeSummary::default();
+
+    for (card_number, card_idx) in filtered_cards {
+        let card = all_cards
+            .get_mut(card_number)
+            .unwrap()
+            .get_mut(*card_idx)
+            .unwrap();
+        if card.img_proxy_en.is_some() {
+            summary.skipped += 1;
+            continue;
+        }
+
+        let from = images_path.join(&card.img);
+        if !from.is_file() {
+            // the official image needs to be downloaded first
+            summary.errors += 1;
+            continue;
+        }
+        let to = images_proxy_path.join(&card.img);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::copy(from, to).unwrap();
+
+        card.img_proxy_en = Some(card.img.clone());
+        card.img_proxy_en_placeholder = true;
+        summary.added += 1;
+    }
+
+    println!(
+        "{} placeholder proxies added ({} missing official images)",
+        summary.added, summary.errors
+    );
+    summary
 }
 
-fn zip_images(file_name: &str, assets_path: &Path, images_path: &Path) {
+/// Returns the number of files that were compressed again
+fn zip_images(file_name: &str, assets_path: &Path, images_path: &Path, incremental: bool) -> u32 {
     let file_path = assets_path.join(file_name).with_extension("zip");
+
+    // reuse the unchanged files from the previous zip
+    let mut previous = None;
+    if incremental && file_path.is_file() {
+        let previous_path = file_path.with_extension("zip.old");
+        fs::rename(&file_path, &previous_path).unwrap();
+        let archive = zip::ZipArchive::new(File::open(&previous_path).unwrap()).unwrap();
+        previous = Some((archive, previous_path));
+    }
+    let mut updated = 0;
+
     let file = File::create(&file_path).unwrap();
 
     let mut zip = zip::ZipWriter::new(file);
@@ -564,12 +1952,26 @@ fn zip_images(file_name: &str, assets_path: &Path, images_path: &Path) {
         // Write file or directory explicitly
         // Some unzip tools unzip files with directory paths correctly, some do not!
         if path.is_file() {
-            zip.start_file_from_path(name, options).unwrap();
             let mut f = File::open(path).unwrap();
-
+            buffer.clear();
             f.read_to_end(&mut buffer).unwrap();
+
+            // compare the size and CRC32 of the content, the images are written again in each run
+            let key = name.to_string_lossy().replace('\\', "/");
+            let state = (buffer.len() as u64, crc32fast::hash(&buffer));
+
+            if let Some((archive, _)) = &mut previous {
+                if let Ok(file) = archive.by_name(&key) {
+                    if (file.size(), file.crc32()) == state {
+                        zip.raw_copy_file(file).unwrap();
+                        continue;
+                    }
+                }
+            }
+            updated += 1;
+
+            zip.start_file_from_path(name, options).unwrap();
             zip.write_all(&buffer).unwrap();
-            buffer.clear();
         } else if !name.as_os_str().is_empty() {
             // Only if not root! Avoids path spec / warning
             // and mapname conversion failed error on unzip
@@ -578,82 +1980,272 @@ fn zip_images(file_name: &str, assets_path: &Path, images_path: &Path) {
     }
     zip.finish().unwrap();
 
-    println!("Created {}", file_path.to_str().unwrap());
+    if let Some((_, previous_path)) = previous {
+        fs::remove_file(previous_path).unwrap();
+    }
+
+    println!(
+        "Created {} ({updated} files updated)",
+        file_path.to_str().unwrap()
+    );
+    updated
 }
 
-fn yuyutei(all_cards: &mut CardsInfo) {
-    let mut urls = IndexMap::new();
+/// Append one JSON line per price update, can be shared between threads
+struct PriceFeed {
+    file: Mutex<File>,
+}
 
-    let scraperapi_key = std::env::var("SCRAPERAPI_API_KEY").ok();
-    if scraperapi_key.is_some() {
-        println!("using scraperapi.com");
+impl PriceFeed {
+    fn open(path: &Path) -> Self {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        PriceFeed {
+            file: Mutex::new(file),
+        }
     }
 
-    // handle multiple pages (one page is 600 cards)
-    for page in 1..=(all_cards.len() as f32 / 600.0).ceil() as u32 {
-        let mut url = Url::parse("https://yuyu-tei.jp/sell/hocg/s/search").unwrap();
-        url.query_pairs_mut()
-            .append_pair("search_word", "")
-            .append_pair("page", page.to_string().as_str());
-        let resp = if let Some(scraperapi_key) = &scraperapi_key {
-            http_client()
-                .get("https://api.scraperapi.com/")
-                .query(&[
-                    ("api_key", scraperapi_key.as_str()),
-                    ("url", url.as_str()),
-                    ("session_number", "123"),
-                ])
-                .timeout(Duration::from_secs(70))
-                .send()
-                .unwrap()
-        } else {
-            http_client().get(url.clone()).send().unwrap()
+    fn append(&self, card: &CardEntry, source: PriceSource, url: &str) {
+        #[derive(Debug, Serialize)]
+        #[serde(rename_all = "snake_case")]
+        struct PriceFeedEntry<'a> {
+            card_number: &'a str,
+            rare: &'a str,
+            source: PriceSource,
+            url: &'a str,
+            timestamp: String,
+        }
+
+        let entry = PriceFeedEntry {
+            card_number: &card.card_number,
+            rare: &card.rare,
+            source,
+            url,
+            timestamp: httpdate::fmt_http_date(SystemTime::now()),
         };
+        let mut line = serde_json::to_string(&entry).unwrap();
+        line.push('\n');
 
-        let content = resp.text().unwrap();
-        // println!("{content}");
+        // one write per line, so the lines are never mixed
+        let mut file = self.file.lock();
+        file.write_all(line.as_bytes()).unwrap();
+        file.flush().unwrap();
+    }
+}
 
-        let document = Html::parse_document(&content);
-        let card_lists = Selector::parse("#card-list3").unwrap();
-        let rarity_select = Selector::parse("h3 span").unwrap();
-        let cards_select = Selector::parse(".card-product").unwrap();
-        let number_select = Selector::parse("span").unwrap();
-        let url_select = Selector::parse("a").unwrap();
+// the selectors used to scrape yuyu-tei.jp
+const YUYUTEI_CARD_LIST: &str = "#card-list3";
+const YUYUTEI_RARITY: &str = "h3 span"; // in card list
+const YUYUTEI_CARD: &str = ".card-product"; // in card list
+const YUYUTEI_NUMBER: &str = "span"; // in card
+const YUYUTEI_URL: &str = "a"; // in card
+
+/// Read the body of a response, without trusting the server to send valid UTF-8
+fn response_text(resp: Response, expected_content_type: &str) -> Result<String, String> {
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(format!("status {status}"));
+    }
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let content_length = resp.content_length();
+
+    let bytes = resp.bytes().map_err(|e| e.to_string())?;
+    decode_body(&content_type, expected_content_type, content_length, &bytes)
+}
 
-        for card_list in document.select(&card_lists) {
-            let rarity: String = card_list
-                .select(&rarity_select)
-                .next()
-                .unwrap()
-                .text()
-                .collect();
-            for card in card_list.select(&cards_select) {
-                let number: String = card.select(&number_select).next().unwrap().text().collect();
-                let url = card.select(&url_select).next().unwrap().attr("href");
-                if let Some(url) = url {
-                    // group them by url
-                    urls.entry(url.to_owned())
-                        .or_insert((number, rarity.clone()));
-                }
-            }
+/// Check that the body is complete and has the expected type, then decode it lossily
+fn decode_body(
+    content_type: &str,
+    expected_content_type: &str,
+    content_length: Option<u64>,
+    bytes: &[u8],
+) -> Result<String, String> {
+    if !content_type.contains(expected_content_type) {
+        return Err(format!("unexpected content type: {content_type:?}"));
+    }
+    if let Some(content_length) = content_length {
+        if bytes.len() as u64 != content_length {
+            return Err(format!(
+                "truncated response: {} of {content_length} bytes",
+                bytes.len()
+            ));
         }
     }
-    println!("Found {} Yuyutei urls...", urls.len());
 
-    let mut url_count = 0;
-    let mut url_skipped = 0;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
 
-    // println!("BEFORE: {urls:#?}");
-    // remove existing urls
-    let mut existing_urls: HashMap<String, String> = HashMap::new();
-    for card in all_cards
-        .values_mut()
-        .flatten()
-        .filter(|c| c.yuyutei_sell_url.is_some())
-    {
-        if let Some(yuyutei_sell_url) = &card.yuyutei_sell_url {
-            if urls.shift_remove(yuyutei_sell_url).is_some() {
-                url_skipped += 1;
+fn yuyutei_page(page: u32, scraperapi_key: &Option<String>) -> Result<String, String> {
+    let mut url = Url::parse("https://yuyu-tei.jp/sell/hocg/s/search").unwrap();
+    url.query_pairs_mut()
+        .append_pair("search_word", "")
+        .append_pair("page", page.to_string().as_str());
+    let resp = if let Some(scraperapi_key) = scraperapi_key {
+        http_client()
+            .get("https://api.scraperapi.com/")
+            .query(&[
+                ("api_key", scraperapi_key.as_str()),
+                ("url", url.as_str()),
+                ("session_number", "123"),
+            ])
+            .timeout(Duration::from_secs(70))
+            .send()
+    } else {
+        http_client().get(url.clone()).send()
+    };
+
+    response_text(resp.map_err(|e| e.to_string())?, "html")
+}
+
+/// Show how many elements each selector matches on the first page, to find which one broke
+fn dump_selectors() {
+    let scraperapi_key = std::env::var("SCRAPERAPI_API_KEY").ok();
+    let content = match yuyutei_page(1, &scraperapi_key) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("WARNING: page 1 skipped: {e}");
+            return;
+        }
+    };
+    let counts = count_yuyutei_selectors(&Html::parse_document(&content));
+
+    println!("yuyu-tei.jp ({} bytes)", content.len());
+    for (selector, count) in counts {
+        let warning = if count == 0 { " <- BROKEN?" } else { "" };
+        println!("  {selector:20} {count}{warning}");
+    }
+}
+
+/// Count the elements matched by each yuyu-tei.jp selector, in scraping order
+fn count_yuyutei_selectors(document: &Html) -> [(&'static str, usize); 5] {
+    let card_lists = Selector::parse(YUYUTEI_CARD_LIST).unwrap();
+    let rarity_select = Selector::parse(YUYUTEI_RARITY).unwrap();
+    let cards_select = Selector::parse(YUYUTEI_CARD).unwrap();
+    let number_select = Selector::parse(YUYUTEI_NUMBER).unwrap();
+    let url_select = Selector::parse(YUYUTEI_URL).unwrap();
+
+    let lists: Vec<_> = document.select(&card_lists).collect();
+    let cards: Vec<_> = lists.iter().flat_map(|l| l.select(&cards_select)).collect();
+    [
+        (YUYUTEI_CARD_LIST, lists.len()),
+        (
+            YUYUTEI_RARITY,
+            lists.iter().flat_map(|l| l.select(&rarity_select)).count(),
+        ),
+        (YUYUTEI_CARD, cards.len()),
+        (
+            YUYUTEI_NUMBER,
+            cards.iter().flat_map(|c| c.select(&number_select)).count(),
+        ),
+        (
+            YUYUTEI_URL,
+            cards.iter().flat_map(|c| c.select(&url_select)).count(),
+        ),
+    ]
+}
+
+fn yuyutei(
+    all_cards: &mut CardsInfo,
+    set_filter: &SetFilter,
+    max_pages: u32,
+    price_feed: Option<&PriceFeed>,
+) -> ScrapeSummary {
+    let mut urls = IndexMap::new();
+
+    let scraperapi_key = std::env::var("SCRAPERAPI_API_KEY").ok();
+    if scraperapi_key.is_some() {
+        println!("using scraperapi.com");
+    }
+
+    let mut invalid_numbers = 0;
+    let mut errors = 0;
+
+    // handle multiple pages (one page is 600 cards)
+    let pages = ((all_cards.len() as f32 / 600.0).ceil() as u32).min(max_pages);
+    for page in 1..=pages {
+        let content = match yuyutei_page(page, &scraperapi_key) {
+            Ok(content) => content,
+            Err(e) => {
+                // the other pages can still be used
+                eprintln!("WARNING: Yuyutei page {page} skipped: {e}");
+                errors += 1;
+                continue;
+            }
+        };
+        // println!("{content}");
+
+        let document = Html::parse_document(&content);
+        let mut page_cards = 0;
+        let card_lists = Selector::parse(YUYUTEI_CARD_LIST).unwrap();
+        let rarity_select = Selector::parse(YUYUTEI_RARITY).unwrap();
+        let cards_select = Selector::parse(YUYUTEI_CARD).unwrap();
+        let number_select = Selector::parse(YUYUTEI_NUMBER).unwrap();
+        let url_select = Selector::parse(YUYUTEI_URL).unwrap();
+
+        for card_list in document.select(&card_lists) {
+            // malformed html, e.g. a truncated page
+            let Some(rarity) = card_list.select(&rarity_select).next() else {
+                continue;
+            };
+            let rarity: String = rarity.text().collect();
+            for card in card_list.select(&cards_select) {
+                page_cards += 1;
+                let Some(number) = card.select(&number_select).next() else {
+                    continue;
+                };
+                let number: String = number.text().collect();
+                // only keep hOCG cards
+                if !is_card_number(number.trim()) {
+                    invalid_numbers += 1;
+                    continue;
+                }
+                let url = card.select(&url_select).next().and_then(|u| u.attr("href"));
+                if let Some(url) = url {
+                    // group them by url
+                    urls.entry(url.to_owned())
+                        .or_insert((number, rarity.clone()));
+                }
+            }
+        }
+
+        // the next pages could have more cards
+        if page == max_pages && page_cards > 0 {
+            eprintln!(
+                "WARNING: Yuyutei stopped at --max-pages {max_pages}, there could be more cards"
+            );
+            errors += 1;
+        }
+    }
+    println!(
+        "Found {} Yuyutei urls... ({invalid_numbers} invalid card numbers)",
+        urls.len()
+    );
+
+    let mut url_count = 0;
+    let mut url_skipped = 0;
+
+    // println!("BEFORE: {urls:#?}");
+    // remove existing urls
+    let mut existing_urls: HashMap<String, String> = HashMap::new();
+    for card in all_cards
+        .values_mut()
+        .flatten()
+        .filter(|c| c.yuyutei_sell_url.is_some())
+    {
+        if let Some(yuyutei_sell_url) = &card.yuyutei_sell_url {
+            if urls.shift_remove(yuyutei_sell_url).is_some() {
+                url_skipped += 1;
             }
             // group by image, some entries are duplicated, like hSD01-016
             existing_urls
@@ -674,15 +2266,22 @@ fn yuyutei(all_cards: &mut CardsInfo) {
         .values_mut()
         .flatten()
         .filter(|c| c.yuyutei_sell_url.is_none())
+        .filter(|c| set_filter.matches(c))
     {
         // look some same image first
         if let Some(yuyutei_sell_url) = existing_urls.get(&card.img) {
             card.yuyutei_sell_url = Some(yuyutei_sell_url.clone());
+            if let Some(price_feed) = price_feed {
+                price_feed.append(card, PriceSource::Yuyutei, yuyutei_sell_url);
+            }
         } else if let Some(urls) = urls.get_mut(&(card.card_number.clone(), card.rare.clone())) {
             if !urls.is_empty() {
                 // take the first url (should be in chronological order, with some exceptions)
                 let yuyutei_sell_url = urls.remove(0);
                 card.yuyutei_sell_url = Some(yuyutei_sell_url.clone());
+                if let Some(price_feed) = price_feed {
+                    price_feed.append(card, PriceSource::Yuyutei, &yuyutei_sell_url);
+                }
                 // group by image, some entries are duplicated
                 existing_urls
                     .entry(card.img.clone())
@@ -702,6 +2301,557 @@ fn yuyutei(all_cards: &mut CardsInfo) {
             println!("MISSING: [{number}, {rare}] - {url}");
         }
     }
+
+    ScrapeSummary {
+        added: url_count,
+        skipped: url_skipped,
+        errors,
+        ..Default::default()
+    }
+}
+
+/// source -> set -> rarity -> card numbers
+type MissingPrices<'a> = BTreeMap<&'a str, BTreeMap<&'a str, BTreeMap<&'a str, Vec<&'a str>>>>;
+
+fn list_missing_prices(all_cards: &CardsInfo, set_filter: &SetFilter) {
+    let missing = missing_prices(all_cards, set_filter);
+    println!("{}", serde_json::to_string_pretty(&missing).unwrap());
+}
+
+/// The released cards without a price url, for each price source
+fn missing_prices<'a>(all_cards: &'a CardsInfo, set_filter: &SetFilter) -> MissingPrices<'a> {
+    let mut missing = MissingPrices::new();
+
+    for card in all_cards
+        .values()
+        .flatten()
+        // unreleased cards don't have prices
+        .filter(|c| c.manage_id.is_some())
+        .filter(|c| set_filter.matches(c))
+    {
+        if card.yuyutei_sell_url.is_none() {
+            missing
+                .entry("yuyutei")
+                .or_default()
+                .entry(card.set_code())
+                .or_default()
+                .entry(&card.rare)
+                .or_default()
+                .push(&card.card_number);
+        }
+    }
+
+    missing
+}
+
+/// Returns the number of broken urls
+fn check_urls(all_cards: &CardsInfo, set_filter: &SetFilter) -> u32 {
+    // some entries share the same url
+    let mut urls: IndexMap<&str, Vec<&CardEntry>> = IndexMap::new();
+    for card in all_cards
+        .values()
+        .flatten()
+        .filter(|c| set_filter.matches(c))
+    {
+        if let Some(url) = &card.yuyutei_sell_url {
+            urls.entry(url).or_default().push(card);
+        }
+    }
+
+    println!("Checking {} urls...", urls.len());
+
+    let mut broken = 0;
+    // one at a time, to not get blocked
+    for (url, cards) in urls {
+        let resp = http_client()
+            .head(url)
+            .timeout(Duration::from_secs(30))
+            .send();
+        let status = match resp {
+            Ok(resp) if resp.status().is_success() => continue,
+            Ok(resp) => resp.status().to_string(),
+            Err(err) => err.to_string(),
+        };
+
+        broken += 1;
+        for card in cards {
+            println!(
+                "BROKEN: [{}, {}] - {url} ({status})",
+                card.card_number, card.rare
+            );
+        }
+    }
+
+    println!("{broken} broken urls");
+    broken
+}
+
+/// Find the images that were updated on the official site, or are missing locally
+fn compare_images_to_source(all_cards: &mut CardsInfo, args: &Args, set_filter: &SetFilter) {
+    let images_path = args.assets_path.join("img");
+
+    let cards: Vec<(String, usize)> = all_cards
+        .values()
+        .flat_map(|cs| cs.iter().enumerate())
+        // unreleased cards don't have images
+        .filter(|c| c.1.manage_id.is_some())
+        .filter(|c| set_filter.matches(c.1))
+        .map(|c| (c.1.card_number.clone(), c.0))
+        .collect();
+
+    println!("Comparing {} images...", cards.len());
+
+    let outdated: Vec<_> = cards
+        .into_par_iter()
+        .filter(|(card_number, card_idx)| {
+            let card = &all_cards[card_number][*card_idx];
+
+            if !images_path.join(&card.img).is_file() {
+                println!(
+                    "MISSING: [{}, {}] - {}",
+                    card.card_number, card.rare, card.img
+                );
+                return true;
+            }
+
+            let img_url = official_image_url(&card.img);
+            let resp = match http_client()
+                .head(&img_url)
+                .header(REFERER, "https://decklog.bushiroad.com/")
+                .timeout(Duration::from_secs(30))
+                .send()
+            {
+                Ok(resp) if resp.status().is_success() => resp,
+                Ok(resp) => {
+                    eprintln!("WARNING: {img_url} ({})", resp.status());
+                    return false;
+                }
+                Err(err) => {
+                    eprintln!("WARNING: {img_url} ({err})");
+                    return false;
+                }
+            };
+
+            let last_modified = resp
+                .headers()
+                .get(LAST_MODIFIED)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| httpdate::parse_http_date(h).ok());
+            let card_last_modified = card
+                .img_last_modified
+                .as_deref()
+                .and_then(|h| httpdate::parse_http_date(h).ok());
+            match image_status(last_modified, card_last_modified) {
+                ImageStatus::Outdated => {
+                    println!(
+                        "OUTDATED: [{}, {}] - {} (official: {}, stored: {})",
+                        card.card_number,
+                        card.rare,
+                        card.img,
+                        last_modified
+                            .map(httpdate::fmt_http_date)
+                            .unwrap_or_default(),
+                        card_last_modified
+                            .map(httpdate::fmt_http_date)
+                            .unwrap_or_default()
+                    );
+                    true
+                }
+                ImageStatus::UnknownDate => {
+                    println!(
+                        "UNKNOWN DATE: [{}, {}] - {}",
+                        card.card_number, card.rare, card.img
+                    );
+                    true
+                }
+                ImageStatus::UpToDate => false,
+            }
+        })
+        .collect();
+
+    println!("{} images differ from the official site", outdated.len());
+
+    if args.fix && !outdated.is_empty() {
+        // make sure they are downloaded again
+        for (card_number, card_idx) in &outdated {
+            all_cards.get_mut(card_number).unwrap()[*card_idx].img_last_modified = None;
+        }
+        let summary = download_images(&outdated, &images_path, all_cards, args);
+        println!("Images: {summary}");
+
+        let card_mapping_file = args.assets_path.join("cards_info.json");
+        write_atomic(&card_mapping_file, args.json_style.to_string(all_cards));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImageStatus {
+    UpToDate,
+    /// The official image is newer than the stored one
+    Outdated,
+    /// The stored image was saved without its date, it could be outdated
+    UnknownDate,
+}
+
+/// Compare the Last-Modified dates of the official and the stored images
+fn image_status(official: Option<SystemTime>, stored: Option<SystemTime>) -> ImageStatus {
+    match (official, stored) {
+        (Some(official), Some(stored)) if official > stored => ImageStatus::Outdated,
+        (Some(_), None) => ImageStatus::UnknownDate,
+        _ => ImageStatus::UpToDate,
+    }
+}
+
+/// Only the filtered sets are written again, the index still lists all of them
+fn save_split_by_set(
+    all_cards: &CardsInfo,
+    path: &Path,
+    json_style: JsonStyle,
+    set_filter: &SetFilter,
+) {
+    let mut sets: BTreeMap<&str, CardsInfo> = BTreeMap::new();
+    for (card_number, cards) in all_cards {
+        let Some(first) = cards.first() else {
+            continue;
+        };
+        sets.entry(first.set_code())
+            .or_default()
+            .insert(card_number.clone(), cards.clone());
+    }
+
+    fs::create_dir_all(path).unwrap();
+    // set code -> file name
+    let mut index = BTreeMap::new();
+    let mut saved = 0;
+    for (set_code, cards) in &sets {
+        let file_name = format!("{set_code}.json");
+        if cards.values().flatten().any(|c| set_filter.matches(c)) {
+            write_atomic(&path.join(&file_name), json_style.to_string(cards));
+            saved += 1;
+        } else if !path.join(&file_name).is_file() {
+            // never saved, can't be loaded
+            continue;
+        }
+        index.insert(*set_code, file_name);
+    }
+    write_atomic(&path.join("index.json"), json_style.to_string(&index));
+
+    println!("Saved {saved} sets to {}", path.display());
+}
+
+fn load_split_by_set(path: &Path) -> CardsInfo {
+    let index: BTreeMap<String, String> =
+        serde_json::from_str(&fs::read_to_string(path.join("index.json")).unwrap()).unwrap();
+
+    let mut all_cards = CardsInfo::new();
+    for file_name in index.values() {
+        let cards: CardsInfo =
+            serde_json::from_str(&fs::read_to_string(path.join(file_name)).unwrap()).unwrap();
+        all_cards.extend(cards);
+    }
+    all_cards
+}
+
+/// Returns the images that are not card shaped, the furthest first
+fn audit_aspect(all_cards: &CardsInfo, assets_path: &Path, set_filter: &SetFilter) -> Vec<PathBuf> {
+    // cards are 400x559
+    const CARD_ASPECT: f64 = 400.0 / 559.0;
+    const TOLERANCE: f64 = 0.02;
+
+    let mut outliers = Vec::new();
+    for card in all_cards
+        .values()
+        .flatten()
+        .filter(|c| set_filter.matches(c))
+    {
+        let images = [
+            Some(assets_path.join("img").join(&card.img)),
+            card.img_proxy_en
+                .as_ref()
+                .map(|img| assets_path.join("img_proxy_en").join(img)),
+        ];
+        for path in images.into_iter().flatten().filter(|p| p.is_file()) {
+            // only reads the header
+            match image::image_dimensions(&path) {
+                Ok((width, height)) => {
+                    let aspect = width as f64 / height as f64;
+                    let deviation = (aspect / CARD_ASPECT - 1.0).abs();
+                    if deviation > TOLERANCE {
+                        outliers.push((deviation, path, width, height));
+                    }
+                }
+                Err(err) => eprintln!("WARNING: cannot read {}: {err}", path.display()),
+            }
+        }
+    }
+
+    outliers.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (deviation, path, width, height) in &outliers {
+        println!(
+            "ASPECT: {} - {width}x{height} ({:.1}% off)",
+            path.display(),
+            deviation * 100.0
+        );
+    }
+    println!("{} aspect ratio outliers", outliers.len());
+    outliers.into_iter().map(|(_, path, _, _)| path).collect()
+}
+
+/// manage_ids increase with the release order, so a set should use a narrow range of them.
+/// An id far below or above the rest of its set is probably a data error or a reused id.
+fn verify_manage_id_monotonic(all_cards: &CardsInfo, set_filter: &SetFilter) -> u32 {
+    let mut sets: BTreeMap<&str, Vec<&CardEntry>> = BTreeMap::new();
+    for card in all_cards
+        .values()
+        .flatten()
+        .filter(|c| c.manage_id.is_some())
+        .filter(|c| set_filter.matches(c))
+    {
+        sets.entry(card.set_code()).or_default().push(card);
+    }
+
+    let mut anomalies = 0;
+    for (set_code, mut cards) in sets {
+        cards.sort_by_key(|c| c.manage_id);
+        let ids: Vec<u32> = cards.iter().filter_map(|c| c.manage_id).collect();
+        let (most, allowed) = manage_id_ranges(&ids);
+
+        for card in cards {
+            let Some(manage_id) = card.manage_id else {
+                continue;
+            };
+            if !allowed.contains(&manage_id) {
+                println!(
+                    "MANAGE_ID OUTLIER: [{}, {}] - {manage_id} ({set_code} is mostly {most:?})",
+                    card.card_number, card.rare
+                );
+                anomalies += 1;
+            }
+        }
+    }
+    println!("{anomalies} manage_id anomalies");
+    anomalies
+}
+
+/// The range of most of the sorted ids (interquartile), and the range of the allowed ids
+fn manage_id_ranges(ids: &[u32]) -> (RangeInclusive<u32>, RangeInclusive<u32>) {
+    // the promo sets can have a few cards in a short range
+    const MIN_FENCE: u32 = 20;
+
+    // interquartile range, to ignore the outliers themselves
+    let q1 = ids[ids.len() / 4];
+    let q3 = ids[ids.len() * 3 / 4];
+    let fence = ((q3 - q1) * 3).max(MIN_FENCE);
+    (q1..=q3, q1.saturating_sub(fence)..=q3.saturating_add(fence))
+}
+
+fn emit_index(all_cards: &CardsInfo, path: &Path, set_filter: &SetFilter) {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    struct IndexEntry<'a> {
+        card_number: &'a str,
+        deck_type: &'a str,
+        rarities: Vec<&'a str>,
+        img: &'a str,
+        img_thumb: Option<&'a str>,
+        img_proxy_en: Option<&'a str>,
+    }
+
+    let index: Vec<_> = all_cards
+        .iter()
+        .filter_map(|(card_number, cards)| {
+            // the oldest entry is the main image
+            let first = cards.first().filter(|c| set_filter.matches(c))?;
+            let mut rarities: Vec<_> = Vec::new();
+            for card in cards {
+                if !rarities.contains(&card.rare.as_str()) {
+                    rarities.push(&card.rare);
+                }
+            }
+            Some(IndexEntry {
+                card_number,
+                deck_type: &first.deck_type,
+                rarities,
+                img: &first.img,
+                img_thumb: first.img_thumb.as_deref(),
+                // placeholders are not real proxies
+                img_proxy_en: cards.iter().find_map(|c| {
+                    c.img_proxy_en
+                        .as_deref()
+                        .filter(|_| !c.img_proxy_en_placeholder)
+                }),
+            })
+        })
+        .collect();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    write_atomic(path, serde_json::to_string(&index).unwrap());
+
+    println!("Wrote {} cards to {}", index.len(), path.display());
+}
+
+/// One row per image, the official one and the English proxy
+fn export_images_index(all_cards: &CardsInfo, path: &Path, set_filter: &SetFilter) {
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    struct ImagesIndexRow<'a> {
+        card_number: &'a str,
+        rare: &'a str,
+        language: &'a str,
+        img: &'a str,
+        manage_id: Option<u32>,
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).unwrap();
+    }
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    let mut rows = 0;
+    for card in all_cards
+        .values()
+        .flatten()
+        .filter(|c| set_filter.matches(c))
+    {
+        let images = [
+            Some(("jp", card.img.as_str())),
+            // placeholders are not real proxies
+            card.img_proxy_en
+                .as_deref()
+                .filter(|_| !card.img_proxy_en_placeholder)
+                .map(|img| ("en", img)),
+        ];
+        for (language, img) in images.into_iter().flatten() {
+            if img.trim().is_empty() {
+                continue;
+            }
+            writer
+                .serialize(ImagesIndexRow {
+                    card_number: &card.card_number,
+                    rare: &card.rare,
+                    language,
+                    img,
+                    manage_id: card.manage_id,
+                })
+                .unwrap();
+            rows += 1;
+        }
+    }
+    write_atomic(path, writer.into_inner().unwrap());
+
+    println!("Wrote {rows} images to {}", path.display());
+}
+
+fn validate(all_cards: &CardsInfo, assets_path: &Path, set_filter: &SetFilter) {
+    let mut issues = 0;
+    issues += validate_delta_art_index(all_cards, set_filter);
+    issues += validate_released_images(all_cards, &assets_path.join("img"), set_filter);
+    issues += validate_image_collisions(all_cards, &assets_path.join("img"), set_filter);
+    println!("{issues} issues found");
+}
+
+/// Each holoDelta art should only be used once per card
+fn validate_delta_art_index(all_cards: &CardsInfo, set_filter: &SetFilter) -> u32 {
+    let mut issues = 0;
+
+    for (card_number, cards) in all_cards {
+        let mut seen = Vec::new();
+        for card in cards.iter().filter(|c| set_filter.matches(c)) {
+            let Some(delta_art_index) = card.delta_art_index else {
+                continue;
+            };
+            if seen.contains(&delta_art_index) {
+                println!(
+                    "DUPLICATE DELTA ART INDEX: [{card_number}, {}] - {delta_art_index} ({})",
+                    card.rare, card.img
+                );
+                issues += 1;
+            } else {
+                seen.push(delta_art_index);
+            }
+        }
+    }
+
+    issues
+}
+
+/// Released cards should have their official image downloaded
+fn validate_released_images(
+    all_cards: &CardsInfo,
+    images_path: &Path,
+    set_filter: &SetFilter,
+) -> u32 {
+    let mut issues = 0;
+
+    for card in all_cards
+        .values()
+        .flatten()
+        .filter(|c| set_filter.matches(c))
+    {
+        let Some(manage_id) = card.manage_id else {
+            continue;
+        };
+        if card.img.trim().is_empty() {
+            println!(
+                "MISSING IMAGE PATH: [{}, {}] - {manage_id}",
+                card.card_number, card.rare
+            );
+            issues += 1;
+        } else if !images_path.join(&card.img).is_file() {
+            println!(
+                "MISSING IMAGE: [{}, {}] - {manage_id} ({})",
+                card.card_number, card.rare, card.img
+            );
+            issues += 1;
+        }
+    }
+
+    issues
+}
+
+/// Different card numbers should never share the same image file content,
+/// it's usually the wrong image saved for a card. Reprints of the same number can.
+fn validate_image_collisions(
+    all_cards: &CardsInfo,
+    images_path: &Path,
+    set_filter: &SetFilter,
+) -> u32 {
+    let mut issues = 0;
+
+    // key by a hash of the content, to not keep every image in memory
+    let mut by_content: HashMap<u64, Vec<&CardEntry>> = HashMap::new();
+    for card in all_cards
+        .values()
+        .flatten()
+        .filter(|c| set_filter.matches(c))
+    {
+        if card.img.trim().is_empty() {
+            continue;
+        }
+        let Ok(content) = fs::read(images_path.join(&card.img)) else {
+            continue;
+        };
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        by_content.entry(hasher.finish()).or_default().push(card);
+    }
+
+    for cards in by_content.values() {
+        let Some((first, others)) = cards.split_first() else {
+            continue;
+        };
+        for card in others.iter().filter(|c| c.card_number != first.card_number) {
+            println!(
+                "IMAGE COLLISION: [{}, {}] - same image as [{}, {}] ({}, {})",
+                card.card_number, card.rare, first.card_number, first.rare, card.img, first.img
+            );
+            issues += 1;
+        }
+    }
+
+    issues
 }
 
 fn import_holodelta(_all_cards: &mut CardsInfo) {
@@ -728,3 +2878,1183 @@ fn import_holodelta(_all_cards: &mut CardsInfo) {
     // // TODO be careful of order, art_index = 0 should be the most negative
     // // probably swap if an earlier art is found
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(card_number: &str, manage_id: Option<u32>, rare: &str) -> CardEntry {
+        CardEntry {
+            card_number: card_number.into(),
+            manage_id,
+            rare: rare.into(),
+            img: format!(
+                "{}/{card_number}_{rare}.webp",
+                card_number.split('-').next().unwrap()
+            ),
+            max: 4,
+            max_override: None,
+            deck_type: "N".into(),
+            img_last_modified: None,
+            img_thumb: None,
+            img_proxy_en: None,
+            img_proxy_en_placeholder: false,
+            yuyutei_sell_url: None,
+            delta_art_index: None,
+        }
+    }
+
+    fn all_sets() -> SetFilter {
+        SetFilter {
+            include: vec![],
+            exclude: vec![],
+        }
+    }
+
+    fn import(all_cards: &mut CardsInfo, cards: Vec<CardEntry>) -> ScrapeSummary {
+        let mut summary = ScrapeSummary::default();
+        update_cards_from_decklog(
+            all_cards,
+            cards,
+            "N",
+            &mut Vec::new(),
+            &mut summary,
+            &HashMap::new(),
+            &all_sets(),
+        );
+        summary
+    }
+
+    #[test]
+    fn merge_duplicate_images_collapses_same_image() {
+        let mut first = card("hSD01-016", Some(16), "C");
+        first.img_proxy_en = Some("hSD01/hSD01-016_C.png".into());
+        first.img_proxy_en_placeholder = true;
+        let mut second = card("hSD01-016", Some(17), "C");
+        second.img_proxy_en = Some("hSD01/hSD01-016_C.png".into());
+        second.img_thumb = Some("hSD01/hSD01-016_C.webp".into());
+        second.max_override = Some(3);
+        let mut all_cards = CardsInfo::from([("hSD01-016".into(), vec![first, second])]);
+
+        assert_eq!(merge_duplicate_images(&mut all_cards, &all_sets()), 1);
+
+        let cards = &all_cards["hSD01-016"];
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].manage_id, Some(16));
+        assert!(!cards[0].img_proxy_en_placeholder);
+        assert_eq!(
+            cards[0].img_thumb.as_deref(),
+            Some("hSD01/hSD01-016_C.webp")
+        );
+        assert_eq!(cards[0].max_override, Some(3));
+        assert_eq!(cards[0].max, 3);
+    }
+
+    #[test]
+    fn duplicate_image_keeps_oldest_manage_id_on_import() {
+        let mut all_cards =
+            CardsInfo::from([("hSD01-016".into(), vec![card("hSD01-016", Some(16), "C")])]);
+
+        // in any page order
+        for cards in [
+            vec![
+                card("hSD01-016", Some(16), "C"),
+                card("hSD01-016", Some(17), "C"),
+            ],
+            vec![
+                card("hSD01-016", Some(17), "C"),
+                card("hSD01-016", Some(16), "C"),
+            ],
+        ] {
+            let summary = import(&mut all_cards, cards);
+            assert_eq!(summary.skipped, 2);
+            assert_eq!(summary.updated, 0);
+            assert_eq!(all_cards["hSD01-016"].len(), 1);
+            assert_eq!(all_cards["hSD01-016"][0].manage_id, Some(16));
+        }
+    }
+
+    #[test]
+    fn zip_incremental_only_updates_changed_files() {
+        let temp = TempDir::new().unwrap();
+        let images_path = temp.path().join("img");
+        fs::create_dir_all(images_path.join("hSD01")).unwrap();
+        fs::write(images_path.join("hSD01/hSD01-001_RR.webp"), b"first").unwrap();
+        fs::write(images_path.join("hSD01/hSD01-002_C.webp"), b"second").unwrap();
+
+        assert_eq!(zip_images("images", temp.path(), &images_path, true), 2);
+
+        // written again with the same content, like a -z run would do
+        fs::write(images_path.join("hSD01/hSD01-001_RR.webp"), b"first").unwrap();
+        fs::write(images_path.join("hSD01/hSD01-002_C.webp"), b"changed").unwrap();
+        assert_eq!(zip_images("images", temp.path(), &images_path, true), 1);
+
+        let mut archive =
+            zip::ZipArchive::new(File::open(temp.path().join("images.zip")).unwrap()).unwrap();
+        let mut content = String::new();
+        archive
+            .by_name("hSD01/hSD01-002_C.webp")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "changed");
+        assert_eq!(archive.len(), 3);
+    }
+
+    #[test]
+    fn decode_body_rejects_bad_pages() {
+        let json = "application/json; charset=utf-8";
+        assert_eq!(
+            decode_body(json, "json", Some(2), b"[]"),
+            Ok("[]".to_owned())
+        );
+        // truncated
+        assert!(decode_body(json, "json", Some(10), b"[{\"car").is_err());
+        // an error page instead of the API response
+        assert!(decode_body("text/html", "json", None, b"<html></html>").is_err());
+        // invalid UTF-8 doesn't panic
+        assert_eq!(
+            decode_body("text/html", "html", None, b"<p>\xff</p>"),
+            Ok("<p>\u{fffd}</p>".to_owned())
+        );
+    }
+
+    #[test]
+    fn manage_id_conflict_doesnt_depend_on_order() {
+        let a = card("hSD01-016", Some(16), "C");
+        let b = card("hSD01-017", Some(16), "C");
+
+        for (stored, expected) in [
+            (CardsInfo::new(), "hSD01-016"),
+            (
+                CardsInfo::from([("hSD01-017".into(), vec![b.clone()])]),
+                "hSD01-017",
+            ),
+        ] {
+            for cards in [vec![a.clone(), b.clone()], vec![b.clone(), a.clone()]] {
+                let mut all_cards = stored.clone();
+                let claimed = resolve_manage_id_conflicts(&all_cards, cards.iter());
+                assert_eq!(claimed[&16], expected);
+
+                let mut summary = ScrapeSummary::default();
+                update_cards_from_decklog(
+                    &mut all_cards,
+                    cards,
+                    "N",
+                    &mut Vec::new(),
+                    &mut summary,
+                    &claimed,
+                    &all_sets(),
+                );
+                assert_eq!(summary.errors, 1);
+                let holders: Vec<_> = all_cards
+                    .values()
+                    .flatten()
+                    .filter(|c| c.manage_id == Some(16))
+                    .map(|c| c.card_number.as_str())
+                    .collect();
+                assert_eq!(holders, [expected]);
+                assert_eq!(all_cards.values().flatten().count(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn prune_sets_removes_only_those_cards_and_files() {
+        let temp = TempDir::new().unwrap();
+        let mut pruned = card("hYS01-001", Some(1), "C");
+        pruned.img_proxy_en = Some(pruned.img.clone());
+        pruned.img_proxy_en_placeholder = true;
+        let kept = card("hSD01-001", Some(2), "RR");
+        for card in [&pruned, &kept] {
+            for folder in ["img", "img_proxy_en"] {
+                let path = temp.path().join(folder).join(&card.img);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, b"image").unwrap();
+            }
+        }
+        let mut all_cards = CardsInfo::from([
+            (pruned.card_number.clone(), vec![pruned.clone()]),
+            (kept.card_number.clone(), vec![kept.clone()]),
+        ]);
+
+        let args = Args::parse_from([
+            "hocg-fan-sim-assets-cli",
+            "--assets-path",
+            temp.path().to_str().unwrap(),
+            "--prune-sets",
+            "hYS01",
+            "--yes",
+        ]);
+        prune_sets(&mut all_cards, &args);
+
+        assert_eq!(all_cards.keys().collect::<Vec<_>>(), ["hSD01-001"]);
+        assert!(!temp.path().join("img").join(&pruned.img).exists());
+        assert!(!temp.path().join("img_proxy_en").join(&pruned.img).exists());
+        assert!(temp.path().join("img").join(&kept.img).exists());
+        assert!(temp.path().join("img_proxy_en").join(&kept.img).exists());
+        assert!(temp.path().join("cards_info.json").is_file());
+    }
+
+    #[test]
+    fn parse_hex_color_rejects_invalid_colors() {
+        assert_eq!(parse_hex_color("#ff8000"), Ok([255, 128, 0]));
+        assert_eq!(parse_hex_color("ffffff"), Ok([255, 255, 255]));
+        assert!(parse_hex_color("#fff").is_err());
+        assert!(parse_hex_color("#gggggg").is_err());
+        // 6 bytes, but not 6 characters
+        assert!(parse_hex_color("a\u{20ac}bc").is_err());
+    }
+
+    #[test]
+    fn handle_alpha_keeps_or_flattens_transparency() {
+        let mut img = image::RgbaImage::new(1, 1);
+        img.put_pixel(0, 0, image::Rgba([0, 0, 0, 0]));
+        let img = DynamicImage::ImageRgba8(img);
+
+        // kept without a background
+        let kept = handle_alpha(img.clone(), None);
+        assert!(kept.color().has_alpha());
+        assert_eq!(kept.into_rgba8().get_pixel(0, 0).0, [0, 0, 0, 0]);
+
+        // flattened on the background
+        let flattened = handle_alpha(img, Some([255, 255, 255]));
+        assert!(!flattened.color().has_alpha());
+        assert_eq!(flattened.into_rgb8().get_pixel(0, 0).0, [255, 255, 255]);
+
+        // no alpha channel to handle
+        let rgb = handle_alpha(DynamicImage::ImageRgb8(RgbImage::new(1, 1)), None);
+        assert!(!rgb.color().has_alpha());
+    }
+
+    #[test]
+    fn import_from_decklog_json_fixture() {
+        let temp = TempDir::new().unwrap();
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/decklog.json");
+        let saved = temp.path().join("decklog.json");
+        let args = Args::parse_from([
+            "hocg-fan-sim-assets-cli",
+            "--decklog-json",
+            fixture,
+            "--save-decklog-json",
+            saved.to_str().unwrap(),
+        ]);
+
+        let mut all_cards = CardsInfo::new();
+        let (filtered_cards, summary) = retrieve_card_info(&mut all_cards, &args, &all_sets());
+        assert_eq!(summary.added, 4);
+        assert_eq!(filtered_cards.len(), 4);
+        let oshi = &all_cards["hSD01-001"][0];
+        assert_eq!(oshi.deck_type, "OSHI");
+        assert_eq!(oshi.manage_id, Some(1));
+        assert_eq!(oshi.img, "hSD01/hSD01-001_OSR.webp");
+        assert_eq!(all_cards["hY01-001"][0].max, 20);
+
+        // the saved file can be imported again, with the same result
+        let args = Args::parse_from([
+            "hocg-fan-sim-assets-cli",
+            "--decklog-json",
+            saved.to_str().unwrap(),
+        ]);
+        let mut reimported = CardsInfo::new();
+        retrieve_card_info(&mut reimported, &args, &all_sets());
+        assert_eq!(
+            serde_json::to_string(&reimported).unwrap(),
+            serde_json::to_string(&all_cards).unwrap()
+        );
+    }
+
+    #[test]
+    fn rebuild_from_images_reads_the_file_names() {
+        let temp = TempDir::new().unwrap();
+        for path in [
+            "img/hSD01/hSD01-001_OSR.webp",
+            "img/hSD01/hSD01-001_SR.webp",
+            "img/hPR/hPR-002_P.webp",
+            "img/hSD01/notes.txt",
+            "img_proxy_en/hSD01/hSD01-001_SR.png",
+        ] {
+            let path = temp.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"image").unwrap();
+        }
+
+        let all_cards = rebuild_from_images(temp.path());
+
+        assert_eq!(
+            all_cards.keys().collect::<Vec<_>>(),
+            ["hPR-002", "hSD01-001"]
+        );
+        let cards = &all_cards["hSD01-001"];
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].rare, "OSR");
+        assert_eq!(cards[0].img, "hSD01/hSD01-001_OSR.webp");
+        assert_eq!(cards[0].img_proxy_en, None);
+        assert_eq!(cards[1].rare, "SR");
+        assert_eq!(
+            cards[1].img_proxy_en.as_deref(),
+            Some("hSD01/hSD01-001_SR.png")
+        );
+        assert!(all_cards.values().flatten().all(|c| c.manage_id.is_none()));
+    }
+
+    #[test]
+    fn set_filter_applies_to_exports() {
+        let temp = TempDir::new().unwrap();
+        let all_cards = CardsInfo::from([
+            ("hPR-001".into(), vec![card("hPR-001", Some(9), "P")]),
+            ("hSD01-001".into(), vec![card("hSD01-001", Some(1), "OSR")]),
+        ]);
+        let set_filter = SetFilter {
+            include: vec![],
+            exclude: vec!["hPR".into()],
+        };
+
+        let csv_path = temp.path().join("images.csv");
+        export_images_index(&all_cards, &csv_path, &set_filter);
+        let csv = fs::read_to_string(csv_path).unwrap();
+        assert!(csv.contains("hSD01-001"));
+        assert!(!csv.contains("hPR-001"));
+
+        let index_path = temp.path().join("index.json");
+        emit_index(&all_cards, &index_path, &set_filter);
+        let index = fs::read_to_string(index_path).unwrap();
+        assert!(index.contains("hSD01-001"));
+        assert!(!index.contains("hPR-001"));
+
+        // the excluded set is kept as it was
+        let split_path = temp.path().join("split");
+        save_split_by_set(&all_cards, &split_path, JsonStyle::Minified, &set_filter);
+        assert!(split_path.join("hSD01.json").is_file());
+        assert!(!split_path.join("hPR.json").exists());
+        assert_eq!(load_split_by_set(&split_path).len(), 1);
+    }
+
+    #[test]
+    fn interrupted_write_keeps_the_original() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("cards_info.json");
+        write_atomic(&path, "{}");
+
+        let result = write_atomic_with(&path, |file| {
+            file.write_all(b"{\"hSD01-001\": [")?;
+            Err(std::io::Error::other("interrupted"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+        // no temporary file left behind
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn dedupe_manage_ids_keeps_the_first_entry() {
+        let first = card("hSD01-001", Some(1), "OSR");
+        let second = card("hSD01-001", Some(1), "SR");
+        let mut all_cards = CardsInfo::from([("hSD01-001".into(), vec![first, second])]);
+
+        dedupe_manage_ids(&mut all_cards, &all_sets());
+
+        let cards = &all_cards["hSD01-001"];
+        assert_eq!(cards.len(), 2);
+        // unreleased first
+        assert_eq!((cards[0].manage_id, cards[0].rare.as_str()), (None, "SR"));
+        assert_eq!(
+            (cards[1].manage_id, cards[1].rare.as_str()),
+            (Some(1), "OSR")
+        );
+    }
+
+    #[test]
+    fn manage_id_outliers_are_flagged() {
+        let mut ids: Vec<u32> = (101..=140).collect();
+        assert_eq!(manage_id_ranges(&ids), (111..=131, 51..=191));
+
+        // a much lower id in a later set
+        ids[6] = 5;
+        ids.sort();
+        let all_cards: CardsInfo = ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| {
+                let card_number = format!("hBP01-{:03}", i + 1);
+                (
+                    card_number.clone(),
+                    vec![card(&card_number, Some(*id), "C")],
+                )
+            })
+            .collect();
+        assert_eq!(verify_manage_id_monotonic(&all_cards, &all_sets()), 1);
+    }
+
+    #[test]
+    fn split_by_set_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let mut hsd01 = card("hSD01-001", Some(1), "OSR");
+        hsd01.yuyutei_sell_url = Some("https://yuyu-tei.jp/sell/hocg/card/hsd01/10001".into());
+        let all_cards = CardsInfo::from([
+            ("hPR-001".into(), vec![card("hPR-001", None, "P")]),
+            (
+                "hSD01-001".into(),
+                vec![hsd01, card("hSD01-001", Some(2), "SR")],
+            ),
+        ]);
+
+        save_split_by_set(&all_cards, temp.path(), JsonStyle::Pretty, &all_sets());
+
+        assert!(temp.path().join("hPR.json").is_file());
+        assert!(temp.path().join("hSD01.json").is_file());
+        assert_eq!(
+            serde_json::to_string(&load_split_by_set(temp.path())).unwrap(),
+            serde_json::to_string(&all_cards).unwrap()
+        );
+    }
+
+    #[test]
+    fn minified_json_round_trip() {
+        let mut oshi = card("hSD01-001", Some(1), "OSR");
+        oshi.img_proxy_en = Some("hSD01/hSD01-001_OSR.png".into());
+        oshi.max_override = Some(1);
+        let all_cards = CardsInfo::from([
+            ("hSD01-001".into(), vec![oshi]),
+            ("hPR-001".into(), vec![card("hPR-001", None, "P")]),
+        ]);
+
+        let minified = JsonStyle::Minified.to_string(&all_cards);
+        let pretty = JsonStyle::Pretty.to_string(&all_cards);
+        // none of the values have spaces
+        assert!(!minified.chars().any(char::is_whitespace));
+        assert!(minified.len() < pretty.len());
+
+        let parsed: CardsInfo = serde_json::from_str(&minified).unwrap();
+        assert_eq!(JsonStyle::Pretty.to_string(&parsed), pretty);
+    }
+
+    #[test]
+    fn encode_webp_to_size_stays_under_the_target() {
+        // noise doesn't compress well, so the quality matters
+        let mut seed = 1u32;
+        let img = RgbImage::from_fn(64, 64, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let [r, g, b, _] = seed.to_le_bytes();
+            Rgb([r, g, b])
+        });
+        let img = DynamicImage::ImageRgb8(img);
+        let encoder = Encoder::from_image(&img).unwrap();
+        let smallest = encoder.encode(0.0).len() as u64;
+        let largest = encoder.encode(100.0).len() as u64;
+        assert!(smallest < largest);
+
+        let target = (smallest + largest) / 2;
+        let webp = encode_webp_to_size(&img, target, "noise");
+        assert!(webp.len() as u64 <= target);
+        assert!(webp.len() as u64 > smallest);
+
+        // too small, falls back to the lowest quality
+        let webp = encode_webp_to_size(&img, 10, "noise");
+        assert_eq!(webp.len() as u64, smallest);
+
+        // no overflow for large targets
+        let webp = encode_webp_to_size(&img, u32::MAX as u64 * 1024, "noise");
+        assert_eq!(webp.len() as u64, largest);
+    }
+
+    #[test]
+    fn duplicate_delta_art_index_is_flagged() {
+        let mut cards = vec![
+            card("hSD01-001", Some(1), "OSR"),
+            card("hSD01-001", Some(2), "SR"),
+            card("hSD01-001", Some(3), "UR"),
+        ];
+        cards[0].delta_art_index = Some(0);
+        cards[1].delta_art_index = Some(1);
+        cards[2].delta_art_index = Some(1);
+        // the same index on another card is fine
+        let mut other = card("hSD01-002", Some(4), "OSR");
+        other.delta_art_index = Some(0);
+        let all_cards = CardsInfo::from([
+            ("hSD01-001".into(), cards),
+            ("hSD01-002".into(), vec![other]),
+        ]);
+
+        assert_eq!(validate_delta_art_index(&all_cards, &all_sets()), 1);
+    }
+
+    #[test]
+    fn image_collisions_across_card_numbers_are_flagged() {
+        let temp = TempDir::new().unwrap();
+        let cards = [
+            (card("hSD01-001", Some(1), "OSR"), "art 1"),
+            // a reprint of the same number can share the image
+            (card("hSD01-001", Some(2), "SR"), "art 1"),
+            // the wrong image saved for another card
+            (card("hSD01-002", Some(3), "OSR"), "art 1"),
+            (card("hSD01-003", Some(4), "C"), "art 3"),
+        ];
+        for (card, content) in &cards {
+            let path = temp.path().join(&card.img);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let mut all_cards = CardsInfo::new();
+        for (card, _) in cards {
+            all_cards
+                .entry(card.card_number.clone())
+                .or_default()
+                .push(card);
+        }
+
+        assert_eq!(
+            validate_image_collisions(&all_cards, temp.path(), &all_sets()),
+            1
+        );
+    }
+
+    #[test]
+    fn decklog_import_counts_added_updated_skipped() {
+        let mut all_cards = CardsInfo::new();
+        let summary = import(
+            &mut all_cards,
+            vec![
+                card("hSD01-001", Some(1), "OSR"),
+                card("hSD01-002", Some(2), "OSR"),
+            ],
+        );
+        assert_eq!((summary.added, summary.updated, summary.skipped), (2, 0, 0));
+
+        let mut changed = card("hSD01-002", Some(2), "OSR");
+        changed.max = 1;
+        let summary = import(
+            &mut all_cards,
+            vec![
+                card("hSD01-001", Some(1), "OSR"),
+                changed,
+                card("hSD01-003", Some(3), "C"),
+            ],
+        );
+        assert_eq!((summary.added, summary.updated, summary.skipped), (1, 1, 1));
+        assert_eq!(
+            summary.to_string(),
+            "1 added, 1 updated, 1 skipped, 0 errors"
+        );
+    }
+
+    #[test]
+    fn yuyutei_selectors_match_saved_page() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/yuyutei.html");
+        let document = Html::parse_document(&fs::read_to_string(fixture).unwrap());
+
+        assert_eq!(
+            count_yuyutei_selectors(&document),
+            [
+                (YUYUTEI_CARD_LIST, 2),
+                (YUYUTEI_RARITY, 2),
+                (YUYUTEI_CARD, 3),
+                (YUYUTEI_NUMBER, 3),
+                (YUYUTEI_URL, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn excluded_sets_are_not_imported() {
+        let mut promo = card("hPR-001", Some(9), "P");
+        promo.img_proxy_en = Some(promo.img.clone());
+        let mut duplicate = card("hPR-001", Some(10), "P");
+        duplicate.manage_id = Some(9);
+        let stored = CardsInfo::from([("hPR-001".into(), vec![promo.clone(), duplicate])]);
+        let exclude_promos = SetFilter {
+            include: vec![],
+            exclude: vec!["hPR".into()],
+        };
+
+        let mut changed = card("hPR-001", Some(9), "SEC");
+        changed.img = "hPR/hPR-001_SEC.webp".into();
+        changed.max = 1;
+        let mut all_cards = stored.clone();
+        let mut summary = ScrapeSummary::default();
+        update_cards_from_decklog(
+            &mut all_cards,
+            vec![changed, card("hSD01-001", Some(9), "OSR")],
+            "OSHI",
+            &mut Vec::new(),
+            &mut summary,
+            &HashMap::new(),
+            &exclude_promos,
+        );
+        assert_eq!((summary.added, summary.skipped), (1, 1));
+        // the repair passes don't touch them either
+        assert_eq!(merge_duplicate_images(&mut all_cards, &exclude_promos), 0);
+        dedupe_manage_ids(&mut all_cards, &exclude_promos);
+
+        assert_eq!(
+            serde_json::to_string(&all_cards["hPR-001"]).unwrap(),
+            serde_json::to_string(&stored["hPR-001"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn watch_schedules_the_next_update() {
+        assert!(Args::try_parse_from(["hocg-fan-sim-assets-cli", "--watch", "0"]).is_err());
+
+        // stopped during the second update, like Ctrl-C would
+        let stop = AtomicBool::new(false);
+        let mut updates = 0;
+        watch(Duration::from_millis(10), &stop, || {
+            updates += 1;
+            if updates == 2 {
+                stop.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
+        });
+        assert_eq!(updates, 2);
+    }
+
+    #[test]
+    fn decklog_import_stops_at_max_pages() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/decklog.json");
+        let import = |max_pages: &str| {
+            let args = Args::parse_from([
+                "hocg-fan-sim-assets-cli",
+                "--decklog-json",
+                fixture,
+                "--max-pages",
+                max_pages,
+            ]);
+            let mut all_cards = CardsInfo::new();
+            let (filtered_cards, summary) = retrieve_card_info(&mut all_cards, &args, &all_sets());
+            (filtered_cards.len(), summary)
+        };
+
+        // the last page is empty
+        let (cards, summary) = import("2");
+        assert_eq!((cards, summary.errors), (4, 0));
+
+        // each deck type still had cards on its last allowed page
+        let (cards, summary) = import("1");
+        assert_eq!((cards, summary.errors), (4, 3));
+    }
+
+    #[test]
+    fn placeholder_proxy_is_replaced_by_a_real_one() {
+        let temp = TempDir::new().unwrap();
+        let images_path = temp.path().join("img");
+        let images_proxy_path = temp.path().join("img_proxy_en");
+        let proxy_path = temp.path().join("proxies");
+        let jp = card("hSD01-001", Some(1), "OSR");
+        fs::create_dir_all(images_path.join("hSD01")).unwrap();
+        fs::write(images_path.join(&jp.img), b"official").unwrap();
+        let mut all_cards = CardsInfo::from([
+            ("hSD01-001".into(), vec![jp.clone()]),
+            // the official image isn't downloaded
+            ("hSD01-002".into(), vec![card("hSD01-002", Some(2), "C")]),
+        ]);
+        let filtered_cards = vec![("hSD01-001".into(), 0), ("hSD01-002".into(), 0)];
+
+        let summary = backfill_english_images(
+            &filtered_cards,
+            &images_path,
+            &images_proxy_path,
+            &mut all_cards,
+        );
+        assert_eq!((summary.added, summary.errors), (1, 1));
+        let placeholder = &all_cards["hSD01-001"][0];
+        assert_eq!(placeholder.img_proxy_en.as_deref(), Some(jp.img.as_str()));
+        assert!(placeholder.img_proxy_en_placeholder);
+        assert_eq!(
+            fs::read(images_proxy_path.join(&jp.img)).unwrap(),
+            b"official"
+        );
+        assert_eq!(all_cards["hSD01-002"][0].img_proxy_en, None);
+
+        // not shown as an English image
+        let index_path = temp.path().join("index.json");
+        emit_index(&all_cards, &index_path, &all_sets());
+        let index: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&index_path).unwrap()).unwrap();
+        assert_eq!(index[0]["img_proxy_en"], serde_json::Value::Null);
+
+        fs::create_dir_all(&proxy_path).unwrap();
+        RgbImage::new(4, 4)
+            .save(proxy_path.join("hSD01-001_OSR.png"))
+            .unwrap();
+        let summary = prepare_proxy_images(
+            &filtered_cards[..1],
+            &images_proxy_path,
+            &mut all_cards,
+            proxy_path,
+            OnImageError::Abort,
+            None,
+        );
+        assert_eq!(summary.added, 1);
+        let proxy = &all_cards["hSD01-001"][0];
+        assert_eq!(proxy.img_proxy_en.as_deref(), Some(jp.img.as_str()));
+        assert!(!proxy.img_proxy_en_placeholder);
+        assert_ne!(
+            fs::read(images_proxy_path.join(&jp.img)).unwrap(),
+            b"official"
+        );
+
+        emit_index(&all_cards, &index_path, &all_sets());
+        let index: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&index_path).unwrap()).unwrap();
+        assert_eq!(index[0]["img_proxy_en"], jp.img.as_str());
+    }
+
+    #[test]
+    fn official_image_url_from_any_extension() {
+        let expected =
+            "https://hololive-official-cardgame.com/wp-content/images/cardlist/hSD01/hSD01-006_RR.png";
+        for img in [
+            "hSD01/hSD01-006_RR.webp",
+            "hSD01/hSD01-006_RR.png",
+            "hSD01/hSD01-006_RR",
+        ] {
+            assert_eq!(official_image_url(img), expected, "{img}");
+        }
+    }
+
+    #[test]
+    fn thumbnail_is_saved_at_the_width() {
+        assert!(
+            Args::try_parse_from(["hocg-fan-sim-assets-cli", "--thumbnail-width", "0"]).is_err()
+        );
+
+        let temp = TempDir::new().unwrap();
+        let thumbnails_path = temp.path().join("thumbnails");
+        let img = DynamicImage::ImageRgb8(RgbImage::new(400, 559));
+        let mut oshi = card("hSD01-001", Some(1), "OSR");
+        oshi.img = "hSD01/hSD01-001_OSR.png".into();
+
+        let img_thumb = save_thumbnail(&img, &thumbnails_path, &oshi.img, 100);
+        assert_eq!(img_thumb, "hSD01/hSD01-001_OSR.webp");
+        let thumb = image::open(thumbnails_path.join(&img_thumb)).unwrap();
+        assert_eq!(thumb.width(), 100);
+
+        // the card browser uses it
+        oshi.img_thumb = Some(img_thumb);
+        let all_cards = CardsInfo::from([("hSD01-001".into(), vec![oshi])]);
+        let index_path = temp.path().join("index.json");
+        emit_index(&all_cards, &index_path, &all_sets());
+        let index: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&index_path).unwrap()).unwrap();
+        assert_eq!(index[0]["img_thumb"], "hSD01/hSD01-001_OSR.webp");
+    }
+
+    #[test]
+    fn corrupt_proxy_is_skipped() {
+        assert!(load_image(b"not an image", "corrupt.png", OnImageError::Skip).is_none());
+
+        let temp = TempDir::new().unwrap();
+        let proxy_path = temp.path().join("proxies");
+        let images_proxy_path = temp.path().join("img_proxy_en");
+        fs::create_dir_all(&proxy_path).unwrap();
+        fs::write(proxy_path.join("hSD01-001_OSR.png"), b"not an image").unwrap();
+        let mut all_cards =
+            CardsInfo::from([("hSD01-001".into(), vec![card("hSD01-001", Some(1), "OSR")])]);
+
+        let summary = prepare_proxy_images(
+            &[("hSD01-001".into(), 0)],
+            &images_proxy_path,
+            &mut all_cards,
+            proxy_path,
+            OnImageError::Skip,
+            None,
+        );
+
+        assert_eq!((summary.added, summary.skipped), (0, 1));
+        assert_eq!(all_cards["hSD01-001"][0].img_proxy_en, None);
+        assert!(!images_proxy_path.exists());
+    }
+
+    #[test]
+    fn empty_decklog_values_dont_overwrite() {
+        let stored = card("hSD01-001", Some(1), "OSR");
+        let mut all_cards = CardsInfo::from([("hSD01-001".into(), vec![stored.clone()])]);
+
+        let mut empty = card("hSD01-001", Some(1), "");
+        empty.img = String::new();
+        let summary = import(&mut all_cards, vec![empty]);
+
+        assert_eq!(summary.updated, 1);
+        let card = &all_cards["hSD01-001"][0];
+        assert_eq!(card.rare, stored.rare);
+        assert_eq!(card.img, stored.img);
+    }
+
+    #[test]
+    fn max_override_survives_reimport() {
+        let mut stored = card("hSD01-001", Some(1), "OSR");
+        stored.max = 3;
+        stored.max_override = Some(3);
+        let mut all_cards = CardsInfo::from([("hSD01-001".into(), vec![stored])]);
+
+        let summary = import(&mut all_cards, vec![card("hSD01-001", Some(1), "OSR")]);
+
+        // nothing changed, Deck Log still has 4
+        assert_eq!(summary.skipped, 1);
+        let card = &all_cards["hSD01-001"][0];
+        assert_eq!((card.max, card.max_override), (3, Some(3)));
+    }
+
+    #[test]
+    fn offline_refuses_network_options() {
+        let network_options = |args: &[&str]| {
+            Args::parse_from([&["hocg-fan-sim-assets-cli", "--offline"], args].concat())
+                .network_options()
+        };
+
+        assert_eq!(
+            network_options(&["--skip-update", "--download-images"]),
+            ["--download-images"]
+        );
+        assert_eq!(network_options(&[]).len(), 1);
+        assert!(network_options(&["--skip-update"]).is_empty());
+        assert!(network_options(&["--decklog-json", "decklog.json", "--validate"]).is_empty());
+    }
+
+    #[test]
+    fn reset_field_clears_every_card() {
+        let temp = TempDir::new().unwrap();
+        let mut cards = [
+            card("hSD01-001", Some(1), "OSR"),
+            card("hSD01-002", Some(2), "C"),
+            card("hPR-001", Some(3), "P"),
+        ];
+        for card in &mut cards {
+            card.yuyutei_sell_url = Some(format!("https://yuyu-tei.jp/{}", card.card_number));
+            card.img_proxy_en = Some(card.img.clone());
+            let path = temp.path().join(&card.img);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"proxy").unwrap();
+        }
+        cards[1].img_proxy_en_placeholder = true;
+        let mut all_cards: CardsInfo = cards
+            .iter()
+            .map(|c| (c.card_number.clone(), vec![c.clone()]))
+            .collect();
+
+        reset_field(
+            &mut all_cards,
+            ResetField::YuyuteiSellUrl,
+            &all_sets(),
+            temp.path(),
+        );
+        assert!(all_cards
+            .values()
+            .flatten()
+            .all(|c| c.yuyutei_sell_url.is_none()));
+
+        // only the placeholder is removed
+        reset_field(
+            &mut all_cards,
+            ResetField::ImgProxyEnPlaceholder,
+            &all_sets(),
+            temp.path(),
+        );
+        assert_eq!(all_cards["hSD01-002"][0].img_proxy_en, None);
+        assert!(!temp.path().join(&cards[1].img).exists());
+        for card in [&cards[0], &cards[2]] {
+            assert_eq!(
+                all_cards[&card.card_number][0].img_proxy_en.as_ref(),
+                Some(&card.img)
+            );
+            assert!(temp.path().join(&card.img).is_file());
+        }
+    }
+
+    #[test]
+    fn deck_type_change_is_reported() {
+        let mut oshi = card("hSD01-001", Some(1), "OSR");
+        oshi.deck_type = "OSHI".into();
+        let all_cards = CardsInfo::from([
+            ("hSD01-001".into(), vec![oshi]),
+            ("hSD01-002".into(), vec![card("hSD01-002", Some(2), "C")]),
+            // new card
+            ("hSD01-003".into(), vec![card("hSD01-003", Some(3), "C")]),
+        ]);
+        let previous = HashMap::from([(1, "N".to_owned()), (2, "N".to_owned())]);
+
+        let changes = deck_type_changes(&previous, &all_cards);
+
+        assert_eq!(changes.len(), 1);
+        let change = &changes[0];
+        assert_eq!(
+            (
+                change.card_number.as_str(),
+                change.manage_id,
+                change.before.as_str(),
+                change.after.as_str()
+            ),
+            ("hSD01-001", 1, "N", "OSHI")
+        );
+    }
+
+    #[test]
+    fn missing_prices_with_partial_coverage() {
+        let mut priced = card("hSD01-001", Some(1), "OSR");
+        priced.yuyutei_sell_url = Some("https://yuyu-tei.jp/sell/hocg/card/hsd01/10001".into());
+        let all_cards = CardsInfo::from([
+            (
+                "hSD01-001".into(),
+                vec![priced, card("hSD01-001", Some(2), "SR")],
+            ),
+            ("hSD01-002".into(), vec![card("hSD01-002", Some(3), "C")]),
+            ("hBP01-001".into(), vec![card("hBP01-001", Some(4), "C")]),
+            // unreleased
+            ("hPR-001".into(), vec![card("hPR-001", None, "P")]),
+        ]);
+
+        let missing = missing_prices(&all_cards, &all_sets());
+
+        assert_eq!(
+            serde_json::to_value(&missing).unwrap(),
+            serde_json::json!({
+                "yuyutei": {
+                    "hBP01": { "C": ["hBP01-001"] },
+                    "hSD01": { "C": ["hSD01-002"], "SR": ["hSD01-001"] },
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn newer_official_image_is_outdated() {
+        let stored = httpdate::parse_http_date("Tue, 01 Oct 2024 00:00:00 GMT").unwrap();
+        let newer = stored + Duration::from_secs(60);
+
+        assert_eq!(
+            image_status(Some(newer), Some(stored)),
+            ImageStatus::Outdated
+        );
+        assert_eq!(
+            image_status(Some(stored), Some(stored)),
+            ImageStatus::UpToDate
+        );
+        assert_eq!(
+            image_status(Some(stored), Some(newer)),
+            ImageStatus::UpToDate
+        );
+        assert_eq!(image_status(Some(newer), None), ImageStatus::UnknownDate);
+        // the official site didn't send a date
+        assert_eq!(image_status(None, Some(stored)), ImageStatus::UpToDate);
+    }
+
+    #[test]
+    fn check_urls_reports_not_found() {
+        // answers 404 for the missing cards, like yuyu-tei.jp
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = stream.read(&mut buffer).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..n]);
+                }
+                let status = if request.starts_with(b"HEAD /missing ") {
+                    "404 Not Found"
+                } else {
+                    "200 OK"
+                };
+                let response =
+                    format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let mut found = card("hSD01-001", Some(1), "OSR");
+        found.yuyutei_sell_url = Some(format!("http://{address}/found"));
+        let mut missing = card("hSD01-002", Some(2), "C");
+        missing.yuyutei_sell_url = Some(format!("http://{address}/missing"));
+        let all_cards = CardsInfo::from([
+            ("hSD01-001".into(), vec![found]),
+            ("hSD01-002".into(), vec![missing]),
+        ]);
+
+        assert_eq!(check_urls(&all_cards, &all_sets()), 1);
+    }
+
+    #[test]
+    fn deterministic_runs_are_identical() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/decklog.json");
+        let args = Args::parse_from([
+            "hocg-fan-sim-assets-cli",
+            "--decklog-json",
+            fixture,
+            "--deterministic",
+        ]);
+
+        let run = || {
+            // the same starting database, like a scheduled update
+            let mut all_cards =
+                CardsInfo::from([("hSD01-003".into(), vec![card("hSD01-003", None, "C")])]);
+            let (filtered_cards, _) = retrieve_card_info(&mut all_cards, &args, &all_sets());
+            (JsonStyle::Pretty.to_string(&all_cards), filtered_cards)
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn price_feed_appends_one_line_per_match() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("feed/prices.jsonl");
+        let card = card("hSD01-001", Some(1), "OSR");
+
+        let feed = PriceFeed::open(&path);
+        feed.append(&card, PriceSource::Yuyutei, "https://yuyu-tei.jp/0");
+        (1..50).into_par_iter().for_each(|i| {
+            feed.append(
+                &card,
+                PriceSource::Yuyutei,
+                &format!("https://yuyu-tei.jp/{i}"),
+            );
+        });
+        drop(feed);
+        // appended to the existing lines
+        PriceFeed::open(&path).append(&card, PriceSource::Yuyutei, "https://yuyu-tei.jp/50");
+
+        let content = fs::read_to_string(&path).unwrap();
+        let mut urls = Vec::new();
+        for line in content.lines() {
+            let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(entry["card_number"], "hSD01-001");
+            assert_eq!(entry["source"], "yuyutei");
+            urls.push(entry["url"].as_str().unwrap().to_owned());
+        }
+        urls.sort();
+        urls.dedup();
+        assert_eq!(urls.len(), 51);
+        assert_eq!(content.lines().count(), 51);
+    }
+
+    #[test]
+    fn import_report_lists_the_phases_that_ran() {
+        let temp = TempDir::new().unwrap();
+        let report_path = temp.path().join("report/import.json");
+        let jp = card("hSD01-001", Some(1), "OSR");
+        let image = temp.path().join("img").join(&jp.img);
+        fs::create_dir_all(image.parent().unwrap()).unwrap();
+        fs::write(image, b"official").unwrap();
+        let mut all_cards = CardsInfo::from([("hSD01-001".into(), vec![jp])]);
+        let args = Args::parse_from([
+            "hocg-fan-sim-assets-cli",
+            "--assets-path",
+            temp.path().to_str().unwrap(),
+            "--skip-update",
+            "--backfill-english-images",
+            "--import-holodelta",
+            "--import-report",
+            report_path.to_str().unwrap(),
+        ]);
+
+        update(&args, &mut all_cards, temp.path(), &all_sets());
+
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&report_path).unwrap()).unwrap();
+        let phases: Vec<_> = report["phases"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(phases, ["backfill_english_images", "holodelta"]);
+        assert_eq!(report["phases"][0]["added"], 1);
+        assert_eq!(report["deck_type_changes"], serde_json::json!([]));
+        assert!(temp.path().join("cards_info.json").is_file());
+    }
+
+    #[test]
+    fn square_image_is_flagged() {
+        let temp = TempDir::new().unwrap();
+        let mut square = card("hSD01-001", Some(1), "OSR");
+        square.img = "hSD01/hSD01-001_OSR.png".into();
+        let mut normal = card("hSD01-002", Some(2), "C");
+        normal.img = "hSD01/hSD01-002_C.png".into();
+        fs::create_dir_all(temp.path().join("img/hSD01")).unwrap();
+        RgbImage::new(100, 100)
+            .save(temp.path().join("img").join(&square.img))
+            .unwrap();
+        RgbImage::new(400, 559)
+            .save(temp.path().join("img").join(&normal.img))
+            .unwrap();
+        let all_cards = CardsInfo::from([
+            ("hSD01-001".into(), vec![square.clone()]),
+            ("hSD01-002".into(), vec![normal]),
+        ]);
+
+        assert_eq!(
+            audit_aspect(&all_cards, temp.path(), &all_sets()),
+            [temp.path().join("img").join(&square.img)]
+        );
+    }
+
+    #[test]
+    fn released_cards_need_their_image() {
+        let temp = TempDir::new().unwrap();
+        let downloaded = card("hSD01-001", Some(1), "OSR");
+        let path = temp.path().join(&downloaded.img);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"image").unwrap();
+        let mut no_path = card("hSD01-002", Some(2), "C");
+        no_path.img = String::new();
+        let all_cards = CardsInfo::from([
+            ("hSD01-001".into(), vec![downloaded]),
+            ("hSD01-002".into(), vec![no_path]),
+            ("hSD01-003".into(), vec![card("hSD01-003", Some(3), "C")]),
+            // unreleased cards don't have an image yet
+            ("hSD01-004".into(), vec![card("hSD01-004", None, "C")]),
+        ]);
+
+        assert_eq!(
+            validate_released_images(&all_cards, temp.path(), &all_sets()),
+            2
+        );
+    }
+
+    #[test]
+    fn assets_path_is_checked_before_scraping() {
+        let temp = TempDir::new().unwrap();
+        let assets_path = temp.path().join("assets");
+        assert_eq!(check_assets_path(&assets_path), Ok(()));
+        assert!(assets_path.is_dir());
+
+        fs::write(assets_path.join("img"), b"not a folder").unwrap();
+        let err = check_assets_path(&assets_path).unwrap_err();
+        assert!(err.ends_with("img should be a folder"), "{err}");
+
+        // the assets folder itself is a file
+        let err = check_assets_path(&assets_path.join("img")).unwrap_err();
+        assert!(err.starts_with("cannot create the assets folder"), "{err}");
+    }
+
+    #[test]
+    fn limit_applies_after_the_set_filter() {
+        let mut all_cards = CardsInfo::new();
+        for card_number in (1..=6)
+            .map(|i| format!("hBP01-{i:03}"))
+            .chain((1..=3).map(|i| format!("hSD01-{i:03}")))
+        {
+            all_cards.insert(card_number.clone(), vec![card(&card_number, None, "C")]);
+        }
+        let cards: Vec<(String, usize)> = all_cards.keys().map(|n| (n.clone(), 0)).collect();
+        let limit = Args::parse_from(["hocg-fan-sim-assets-cli", "--limit", "5"]).limit;
+
+        let mut filtered_cards = cards.clone();
+        select_cards(&mut filtered_cards, &all_cards, &all_sets(), limit);
+        assert_eq!(filtered_cards, cards[..5]);
+
+        // the limit is not used up by the other sets
+        let mut filtered_cards = cards.clone();
+        let hsd01 = SetFilter {
+            include: vec!["hSD01".into()],
+            exclude: vec![],
+        };
+        select_cards(&mut filtered_cards, &all_cards, &hsd01, Some(2));
+        assert_eq!(filtered_cards, cards[6..8]);
+    }
+}
@@ -17,17 +17,57 @@ pub struct CardEntry {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub max: u32, // from Deck Log
     #[serde(default)]
+    pub max_override: Option<u32>, // manual correction, replaces max on import
+    #[serde(default)]
     pub deck_type: String, // from Deck Log
     #[serde(default)]
     pub img_last_modified: Option<String>,
     #[serde(default)]
+    pub img_thumb: Option<String>,
+    #[serde(default)]
     pub img_proxy_en: Option<String>,
     #[serde(default)]
+    pub img_proxy_en_placeholder: bool, // the proxy is a copy of the official image
+    #[serde(default)]
     pub yuyutei_sell_url: Option<String>,
     #[serde(default)]
     pub delta_art_index: Option<u32>,
 }
 
+impl CardEntry {
+    /// The set part of the card number e.g. hSD01 for hSD01-001
+    pub fn set_code(&self) -> &str {
+        self.card_number
+            .split_once('-')
+            .map(|(set, _)| set)
+            .unwrap_or(&self.card_number)
+    }
+}
+
+/// Looks like a hOCG card number e.g. hSD01-001, hPR-001, hY01-001
+pub fn is_card_number(s: &str) -> bool {
+    let Some((set_code, number)) = s.split_once('-') else {
+        return false;
+    };
+    let Some(set_code) = set_code.strip_prefix('h') else {
+        return false;
+    };
+    let letters = set_code.trim_end_matches(|c: char| c.is_ascii_digit());
+    !letters.is_empty()
+        && letters.chars().all(|c| c.is_ascii_uppercase())
+        && number.len() == 3
+        && number.chars().all(|c| c.is_ascii_digit())
+}
+
+/// All the cards of a set, by card number then by oldest to latest
+pub fn cards_in_set<'a>(all_cards: &'a CardsInfo, set_code: &str) -> Vec<&'a CardEntry> {
+    all_cards
+        .values()
+        .flatten()
+        .filter(|c| c.set_code().eq_ignore_ascii_case(set_code))
+        .collect()
+}
+
 fn deserialize_number_from_string<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
     D: Deserializer<'de>,
@@ -71,3 +111,69 @@ where
         None => Ok(None),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_numbers() {
+        for s in ["hSD01-001", "hBP01-104", "hPR-001", "hY01-001", "hYS01-010"] {
+            assert!(is_card_number(s), "{s}");
+        }
+        for s in [
+            "",
+            "hSD01",
+            "SD01-001",
+            "hsd01-001",
+            "h01-001",
+            "hSD01-01",
+            "hSD01-0001",
+            "hSD01-00a",
+            "WS-001",
+        ] {
+            assert!(!is_card_number(s), "{s}");
+        }
+    }
+
+    #[test]
+    fn cards_of_a_set() {
+        let card = |card_number: &str, manage_id: Option<u32>| CardEntry {
+            card_number: card_number.into(),
+            manage_id,
+            rare: "C".into(),
+            img: String::new(),
+            max: 4,
+            max_override: None,
+            deck_type: "N".into(),
+            img_last_modified: None,
+            img_thumb: None,
+            img_proxy_en: None,
+            img_proxy_en_placeholder: false,
+            yuyutei_sell_url: None,
+            delta_art_index: None,
+        };
+        let all_cards = CardsInfo::from([
+            ("hSD01-002".into(), vec![card("hSD01-002", Some(3))]),
+            (
+                "hSD01-001".into(),
+                vec![card("hSD01-001", None), card("hSD01-001", Some(1))],
+            ),
+            ("hSD010-001".into(), vec![card("hSD010-001", Some(2))]),
+            ("hBP01-001".into(), vec![card("hBP01-001", Some(4))]),
+        ]);
+
+        let cards: Vec<_> = cards_in_set(&all_cards, "hsd01")
+            .into_iter()
+            .map(|c| (c.card_number.as_str(), c.manage_id))
+            .collect();
+        assert_eq!(
+            cards,
+            [
+                ("hSD01-001", None),
+                ("hSD01-001", Some(1)),
+                ("hSD01-002", Some(3))
+            ]
+        );
+    }
+}